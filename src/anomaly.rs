@@ -0,0 +1,337 @@
+//! Detection of key events that don't add up, like releases of keys that were
+//! never pressed.
+//!
+//! Window and device events are tracked separately. A key that is held
+//! according to one of them but not the other when a table ends is reported in
+//! the table's summary, since the events of a single key press may be split
+//! across two tables otherwise. Each of these is only reported once, until
+//! there's another event for the key.
+
+use std::collections::HashSet;
+
+use serde_json::json;
+use winit::{event::ElementState, keyboard::KeyCode};
+
+use crate::{key_code_to_string, record::RecordedEvent, Cell};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Anomaly {
+    /// A press of a key that is already held, which isn't a repeat.
+    PressedTwice,
+    ReleaseWithoutPress,
+    RepeatWithoutPress,
+    /// A key that was held when the window lost focus, and wasn't released
+    /// by the time the window got it back.
+    HeldAcrossFocusLoss,
+    HeldByWindowOnly,
+    HeldByDeviceOnly,
+}
+
+impl Anomaly {
+    fn description(self) -> &'static str {
+        match self {
+            Anomaly::PressedTwice => "Pressed twice",
+            Anomaly::ReleaseWithoutPress => "Release without press",
+            Anomaly::RepeatWithoutPress => "Repeat without press",
+            Anomaly::HeldAcrossFocusLoss => "Held across focus loss",
+            Anomaly::HeldByWindowOnly => "Held by window only",
+            Anomaly::HeldByDeviceOnly => "Held by device only",
+        }
+    }
+}
+
+pub struct AnomalyDetector {
+    focused: bool,
+    window_held: HashSet<KeyCode>,
+    device_held: HashSet<KeyCode>,
+    /// Keys that were held by the device when the window lost focus. Their
+    /// releases may or may not be seen, so they aren't flagged either way.
+    device_held_before_focus_loss: HashSet<KeyCode>,
+    /// Keys that were held when the table control button was pressed. The
+    /// releases of a table control chord's keys are taken out, so theirs
+    /// aren't flagged either way.
+    forgotten: HashSet<KeyCode>,
+    /// Whether the window and device events can be compared. On platforms
+    /// without device events, every held key would be held by the window only.
+    device_events_seen: bool,
+    /// Keys whose disagreement has been reported already.
+    disagreements_reported: HashSet<KeyCode>,
+    /// The anomalies in the current table.
+    anomalies: Vec<(KeyCode, Anomaly)>,
+}
+
+impl AnomalyDetector {
+    pub fn new() -> Self {
+        Self {
+            focused: true,
+            window_held: HashSet::new(),
+            device_held: HashSet::new(),
+            device_held_before_focus_loss: HashSet::new(),
+            forgotten: HashSet::new(),
+            device_events_seen: false,
+            disagreements_reported: HashSet::new(),
+            anomalies: Vec::new(),
+        }
+    }
+
+    /// Returns what's wrong with the event, if anything.
+    pub fn handle_event(&mut self, event: &RecordedEvent) -> Option<Cell> {
+        let found: Vec<_> = match event {
+            RecordedEvent::KeyboardInput { event, .. } => {
+                let key_code = event.physical_key;
+                self.disagreements_reported.remove(&key_code);
+                let forgotten = self.forgotten.contains(&key_code);
+                let anomaly = match (event.state, event.repeat) {
+                    (ElementState::Pressed, false) => {
+                        self.forgotten.remove(&key_code);
+                        let inserted = self.window_held.insert(key_code);
+                        if !inserted {
+                            Some(Anomaly::PressedTwice)
+                        } else {
+                            None
+                        }
+                    }
+                    (ElementState::Pressed, true) => {
+                        if !self.window_held.contains(&key_code) && !forgotten {
+                            Some(Anomaly::RepeatWithoutPress)
+                        } else {
+                            None
+                        }
+                    }
+                    (ElementState::Released, _) => {
+                        let removed = self.window_held.remove(&key_code);
+                        if !removed && !forgotten {
+                            Some(Anomaly::ReleaseWithoutPress)
+                        } else {
+                            None
+                        }
+                    }
+                };
+                anomaly
+                    .map(|anomaly| (key_code, anomaly))
+                    .into_iter()
+                    .collect()
+            }
+            // Device events are ignored without focus, unless keys are held.
+            RecordedEvent::DeviceKey {
+                physical_key,
+                state,
+                ..
+            } if self.focused || !self.window_held.is_empty() => {
+                self.device_events_seen = true;
+                self.disagreements_reported.remove(physical_key);
+                match state {
+                    // Device events repeat by pressing the key again.
+                    ElementState::Pressed => {
+                        self.device_held.insert(*physical_key);
+                        self.device_held_before_focus_loss.remove(physical_key);
+                        self.forgotten.remove(physical_key);
+                        Vec::new()
+                    }
+                    ElementState::Released
+                        if !self.device_held.remove(physical_key)
+                            && !self.device_held_before_focus_loss.remove(physical_key)
+                            && !self.forgotten.contains(physical_key) =>
+                    {
+                        vec![(*physical_key, Anomaly::ReleaseWithoutPress)]
+                    }
+                    ElementState::Released => Vec::new(),
+                }
+            }
+            RecordedEvent::TableControl => {
+                self.forgotten.extend(self.window_held.drain());
+                self.forgotten.extend(self.device_held.drain());
+                Vec::new()
+            }
+            RecordedEvent::Focused { focused } => {
+                self.focused = *focused;
+                // Releases of device keys aren't seen without focus.
+                self.device_held_before_focus_loss
+                    .extend(self.device_held.drain());
+                if *focused {
+                    let mut held: Vec<_> = self.window_held.drain().collect();
+                    held.sort_by_key(key_code_to_string);
+                    held.into_iter()
+                        .map(|key_code| (key_code, Anomaly::HeldAcrossFocusLoss))
+                        .collect()
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        let (_, anomaly) = *found.first()?;
+        self.anomalies.extend(found.iter().copied());
+        let keys: Vec<_> = found.iter().map(|(key_code, _)| key_code).collect();
+        let text = match anomaly {
+            Anomaly::HeldAcrossFocusLoss => {
+                let keys: Vec<_> = keys
+                    .iter()
+                    .map(|key_code| key_code_to_string(key_code))
+                    .collect();
+                format!("{}: {}", anomaly.description(), keys.join(", "))
+            }
+            _ => anomaly.description().to_string(),
+        };
+        let value = json!({ "anomaly": format!("{:?}", anomaly), "keys": keys });
+        Some(Cell::typed(text, &value))
+    }
+
+    /// Sums up the anomalies since the last summary, including keys that the
+    /// window and device events disagree on, along with their number.
+    pub fn summary(&mut self) -> (Cell, usize) {
+        if self.device_events_seen {
+            let mut disagreements: Vec<_> = self
+                .window_held
+                .difference(&self.device_held)
+                .map(|key_code| (*key_code, Anomaly::HeldByWindowOnly))
+                .chain(
+                    self.device_held
+                        .difference(&self.window_held)
+                        .map(|key_code| (*key_code, Anomaly::HeldByDeviceOnly)),
+                )
+                .collect();
+            disagreements.retain(|(key_code, _)| self.disagreements_reported.insert(*key_code));
+            disagreements.sort_by_key(|(key_code, _)| key_code_to_string(key_code));
+            self.anomalies.extend(disagreements);
+        }
+
+        let anomalies = std::mem::take(&mut self.anomalies);
+        let text = if anomalies.is_empty() {
+            "No anomalies".to_string()
+        } else {
+            let list: Vec<_> = anomalies
+                .iter()
+                .map(|(key_code, anomaly)| {
+                    format!("{} {}", key_code_to_string(key_code), anomaly.description())
+                })
+                .collect();
+            format!("{} anomalies: {}", anomalies.len(), list.join(", "))
+        };
+        let value: Vec<_> = anomalies
+            .iter()
+            .map(|(key_code, anomaly)| json!({ "anomaly": format!("{:?}", anomaly), "key": key_code }))
+            .collect();
+        (Cell::typed(text, &value), anomalies.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{device_key, key};
+
+    #[test]
+    fn unbalanced_events_are_flagged() {
+        let mut detector = AnomalyDetector::new();
+        let mut check = |event| detector.handle_event(&event).map(|cell| cell.text);
+
+        assert_eq!(
+            check(device_key(KeyCode::KeyA, ElementState::Pressed)),
+            None
+        );
+        assert_eq!(
+            check(key(KeyCode::KeyA, ElementState::Pressed, false)),
+            None
+        );
+        assert_eq!(check(key(KeyCode::KeyA, ElementState::Pressed, true)), None);
+        assert_eq!(
+            check(key(KeyCode::KeyA, ElementState::Pressed, false)).as_deref(),
+            Some("Pressed twice")
+        );
+        assert_eq!(
+            check(key(KeyCode::KeyB, ElementState::Pressed, true)).as_deref(),
+            Some("Repeat without press")
+        );
+        assert_eq!(
+            check(key(KeyCode::KeyC, ElementState::Released, false)).as_deref(),
+            Some("Release without press")
+        );
+        assert_eq!(
+            check(device_key(KeyCode::KeyC, ElementState::Released)).as_deref(),
+            Some("Release without press")
+        );
+        assert_eq!(check(RecordedEvent::Focused { focused: false }), None);
+        assert_eq!(
+            check(RecordedEvent::Focused { focused: true }).as_deref(),
+            Some("Held across focus loss: KeyA")
+        );
+
+        let summary = detector.summary().0.text;
+        assert!(
+            summary.starts_with("5 anomalies: KeyA Pressed twice, "),
+            "{}",
+            summary
+        );
+        assert_eq!(detector.summary().0.text, "No anomalies");
+    }
+
+    #[test]
+    fn window_and_device_disagreements_are_summed_up() {
+        let mut detector = AnomalyDetector::new();
+        detector.handle_event(&key(KeyCode::KeyA, ElementState::Pressed, false));
+        detector.handle_event(&device_key(KeyCode::KeyB, ElementState::Pressed));
+
+        assert_eq!(
+            detector.summary().0.text,
+            "2 anomalies: KeyA Held by window only, KeyB Held by device only"
+        );
+        assert_eq!(detector.summary().0.text, "No anomalies");
+    }
+
+    #[test]
+    fn keys_released_without_focus_are_not_flagged() {
+        let mut detector = AnomalyDetector::new();
+        let mut check = |event| detector.handle_event(&event).map(|cell| cell.text);
+
+        for key_code in [KeyCode::KeyA, KeyCode::KeyB].iter() {
+            check(device_key(*key_code, ElementState::Pressed));
+            check(key(*key_code, ElementState::Pressed, false));
+        }
+        assert_eq!(check(RecordedEvent::Focused { focused: false }), None);
+        // The window still holds KeyB, so the release of KeyA is seen.
+        assert_eq!(
+            check(device_key(KeyCode::KeyA, ElementState::Released)),
+            None
+        );
+        assert_eq!(
+            check(RecordedEvent::Focused { focused: true }).as_deref(),
+            Some("Held across focus loss: KeyA, KeyB")
+        );
+        assert_eq!(
+            check(device_key(KeyCode::KeyB, ElementState::Released)),
+            None
+        );
+        assert_eq!(
+            check(device_key(KeyCode::KeyB, ElementState::Released)).as_deref(),
+            Some("Release without press")
+        );
+    }
+
+    #[test]
+    fn keys_held_through_the_table_control_button_are_forgotten() {
+        let mut detector = AnomalyDetector::new();
+        let mut check = |event| detector.handle_event(&event).map(|cell| cell.text);
+
+        for key_code in [KeyCode::ControlRight, KeyCode::KeyA].iter() {
+            check(device_key(*key_code, ElementState::Pressed));
+            check(key(*key_code, ElementState::Pressed, false));
+        }
+        assert_eq!(check(RecordedEvent::TableControl), None);
+        assert_eq!(check(key(KeyCode::KeyA, ElementState::Pressed, true)), None);
+        assert_eq!(
+            check(device_key(KeyCode::ControlRight, ElementState::Pressed)),
+            None
+        );
+        assert_eq!(
+            check(key(KeyCode::ControlRight, ElementState::Pressed, false)),
+            None
+        );
+        for key_code in [KeyCode::ControlRight, KeyCode::KeyA].iter() {
+            assert_eq!(check(device_key(*key_code, ElementState::Released)), None);
+            assert_eq!(check(key(*key_code, ElementState::Released, false)), None);
+        }
+        assert_eq!(detector.summary().0.text, "No anomalies");
+    }
+}
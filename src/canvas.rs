@@ -0,0 +1,95 @@
+//! Drawing into the softbuffer frame buffer.
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+pub const WHITE: u32 = 0x00ff_ffff;
+pub const BLACK: u32 = 0x0000_0000;
+
+/// A frame buffer of `0RGB` pixels, with text drawn at an integer scale.
+pub struct Canvas<'a> {
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+    scale: usize,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(pixels: &'a mut [u32], width: usize, height: usize, scale: usize) -> Self {
+        debug_assert_eq!(pixels.len(), width * height);
+        Self {
+            pixels,
+            width,
+            height,
+            scale: scale.max(1),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The horizontal distance between two characters.
+    pub fn cell_width(&self) -> usize {
+        (GLYPH_WIDTH + 1) * self.scale
+    }
+
+    /// The vertical distance between two lines of text.
+    pub fn line_height(&self) -> usize {
+        (GLYPH_HEIGHT + 3) * self.scale
+    }
+
+    /// Fills a rectangle, clipped to the canvas.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        let x_end = (x + width).min(self.width);
+        let y_end = (y + height).min(self.height);
+        for y in y.min(y_end)..y_end {
+            let row = y * self.width;
+            for pixel in &mut self.pixels[row + x.min(x_end)..row + x_end] {
+                *pixel = color;
+            }
+        }
+    }
+
+    /// Draws `text` with its top left corner at `(x, y)`.
+    ///
+    /// Characters take up as many cells as they would in a terminal, so text
+    /// lines up the same way it does in the markdown tables.
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, color: u32) {
+        // Center the glyphs vertically in their line.
+        let y = y + self.scale;
+        let mut x = x;
+        for c in text.chars() {
+            let cells = c.width().unwrap_or(0);
+            if cells == 0 {
+                continue;
+            }
+            if x >= self.width {
+                break;
+            }
+            self.draw_glyph(x, y, font::glyph(c), color);
+            x += cells * self.cell_width();
+        }
+    }
+
+    fn draw_glyph(&mut self, x: usize, y: usize, glyph: &[u8; GLYPH_HEIGHT], color: u32) {
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    self.fill_rect(
+                        x + column * self.scale,
+                        y + row * self.scale,
+                        self.scale,
+                        self.scale,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! A walk through every key of a physical layout, for checking what a backend
+//! reports for each of them.
+//!
+//! Each key is asked for under each [`Combination`] of modifiers in turn.
+//! Holding the modifiers is up to the tester, and presses of other keys or
+//! with the wrong modifiers held are ignored. The table control button skips
+//! the key that is asked for, for keys that the keyboard doesn't have.
+//!
+//! Once every key has been pressed or skipped, the results are printed as a
+//! mapping table with a row for every key and combination.
+
+use std::collections::{HashMap, HashSet};
+
+use winit::{event::ElementState, keyboard::KeyCode};
+
+use crate::{
+    column, key_code_to_string, key_to_string, key_without_modifiers,
+    layout::{KeyPosition, PhysicalLayout},
+    nice_text,
+    record::{KeyEventRecord, RecordedEvent},
+    text_with_all_modifiers, Cell, Table, TableColumn, TablePrinter,
+};
+
+/// Keys that make up the combinations or change what the other keys do, which
+/// are left out of the checklist.
+const MODIFIER_KEYS: &[KeyCode] = &[
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::SuperLeft,
+    KeyCode::SuperRight,
+    KeyCode::CapsLock,
+    KeyCode::NumLock,
+    KeyCode::ScrollLock,
+];
+
+/// The modifiers held while pressing a key. AltGr is the right Alt key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Combination {
+    None,
+    Shift,
+    AltGr,
+    ShiftAltGr,
+}
+
+impl Combination {
+    const ALL: [Combination; 4] = [
+        Combination::None,
+        Combination::Shift,
+        Combination::AltGr,
+        Combination::ShiftAltGr,
+    ];
+
+    fn new(shift: bool, alt_gr: bool) -> Self {
+        match (shift, alt_gr) {
+            (false, false) => Combination::None,
+            (true, false) => Combination::Shift,
+            (false, true) => Combination::AltGr,
+            (true, true) => Combination::ShiftAltGr,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|combination| combination.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Combination::None => "None",
+            Combination::Shift => "Shift",
+            Combination::AltGr => "AltGr",
+            Combination::ShiftAltGr => "Shift+AltGr",
+        }
+    }
+}
+
+/// Keeps track of the modifier keys that are held, to tell which
+/// [`Combination`] a key is pressed with.
+#[derive(Default)]
+pub struct HeldModifiers {
+    held: HashSet<KeyCode>,
+}
+
+impl HeldModifiers {
+    pub fn handle_event(&mut self, event: &RecordedEvent) {
+        match event {
+            RecordedEvent::KeyboardInput { event, .. } => match event.state {
+                ElementState::Pressed => {
+                    self.held.insert(event.physical_key);
+                }
+                ElementState::Released => {
+                    self.held.remove(&event.physical_key);
+                }
+            },
+            // A modifier released in another window would stay held and keep
+            // the asked for combination from ever matching.
+            RecordedEvent::Focused { focused: false } => self.held.clear(),
+            // The releases of a table control chord's keys are taken out.
+            RecordedEvent::TableControl => self.held.clear(),
+            _ => {}
+        }
+    }
+
+    fn is_held(&self, key_code: KeyCode) -> bool {
+        self.held.contains(&key_code)
+    }
+
+    /// The combination that is held, or `None` if other modifiers are held
+    /// too.
+    pub fn combination(&self) -> Option<Combination> {
+        let alt_gr = self.is_held(KeyCode::AltRight);
+        // Windows reports AltGr as the left Control and the right Alt key.
+        let control =
+            !alt_gr && (self.is_held(KeyCode::ControlLeft) || self.is_held(KeyCode::ControlRight));
+        let other = self.is_held(KeyCode::AltLeft)
+            || self.is_held(KeyCode::SuperLeft)
+            || self.is_held(KeyCode::SuperRight);
+        if control || other {
+            return None;
+        }
+        let shift = self.is_held(KeyCode::ShiftLeft) || self.is_held(KeyCode::ShiftRight);
+        Some(Combination::new(shift, alt_gr))
+    }
+}
+
+pub struct Checklist {
+    keys: Vec<KeyPosition>,
+    /// The number of keys that have been pressed or skipped. All keys are
+    /// asked for without modifiers first, then with Shift, and so on.
+    position: usize,
+    held: HeldModifiers,
+    results: HashMap<(KeyCode, Combination), KeyEventRecord>,
+    table: Table,
+}
+
+impl Checklist {
+    pub fn new(layout: PhysicalLayout) -> Self {
+        let keys = layout
+            .keys()
+            .into_iter()
+            .filter(|key| !MODIFIER_KEYS.contains(&key.key_code))
+            .collect();
+        Self {
+            keys,
+            position: 0,
+            held: HeldModifiers::default(),
+            results: HashMap::new(),
+            table: build_mapping_table(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len() * Combination::ALL.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.len()
+    }
+
+    /// The key that is asked for, and the modifiers it should be pressed with.
+    fn current(&self) -> Option<(&KeyPosition, Combination)> {
+        if self.is_finished() {
+            return None;
+        }
+        let key = &self.keys[self.position % self.keys.len()];
+        Some((key, Combination::ALL[self.position / self.keys.len()]))
+    }
+
+    /// What the tester should do next, or `None` once the checklist is done.
+    pub fn prompt(&self) -> Option<String> {
+        let (key, combination) = self.current()?;
+        let modifiers = match combination {
+            Combination::None => "no modifiers".to_string(),
+            combination => format!("{} held", combination.name()),
+        };
+        Some(format!(
+            "Press {} ({}) with {} ({}/{})",
+            key.label,
+            key_code_to_string(&key.key_code),
+            modifiers,
+            self.position + 1,
+            self.len(),
+        ))
+    }
+
+    /// Returns whether the checklist moved on to the next key.
+    pub fn handle_event(&mut self, event: &RecordedEvent) -> bool {
+        let (key_code, combination) = match self.current() {
+            Some((key, combination)) => (key.key_code, combination),
+            None => return false,
+        };
+        self.held.handle_event(event);
+        match event {
+            RecordedEvent::KeyboardInput { event, .. } => {
+                let is_expected = event.state == ElementState::Pressed
+                    && !event.repeat
+                    && event.physical_key == key_code
+                    && self.held.combination() == Some(combination);
+                if is_expected {
+                    self.results.insert((key_code, combination), event.clone());
+                    self.position += 1;
+                }
+                is_expected
+            }
+            RecordedEvent::TableControl => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Prints the mapping table, with the keys in layout order. Keys that were
+    /// skipped or not asked for yet only have their key code and modifiers.
+    pub fn print<P: TablePrinter + ?Sized>(&self, table_printer: &mut P) {
+        table_printer.begin_new_table(&self.table);
+        for key in &self.keys {
+            for combination in Combination::ALL.iter() {
+                let row = self
+                    .table
+                    .print_table_line()
+                    .column_with(column::KEY_CODE, || {
+                        Cell::typed(key_code_to_string(&key.key_code), &key.key_code)
+                    })
+                    .column(column::MODIFIERS, combination.name());
+                let row = match self.results.get(&(key.key_code, *combination)) {
+                    Some(event) => row
+                        .column_with(column::KEY, || {
+                            Cell::typed(key_to_string(&event.logical_key), &event.logical_key)
+                        })
+                        .column_with(column::TEXT, || {
+                            let text = event.text.as_ref().map(nice_text).unwrap_or_default();
+                            Cell::typed(text, &event.text)
+                        })
+                        .column_with(column::KEY_NO_MOD, || {
+                            Cell::typed(key_without_modifiers(event), &event.key_without_modifiers)
+                        })
+                        .column_with(column::TEXT_ALL_MODS, || {
+                            Cell::typed(
+                                text_with_all_modifiers(event),
+                                &event.text_with_all_modifiers,
+                            )
+                        }),
+                    None => row,
+                };
+                table_printer.print_row(&self.table, &row.finish());
+            }
+        }
+    }
+}
+
+#[rustfmt::skip]
+fn build_mapping_table() -> Table {
+    let mut table = Table::new();
+    table.add_column(TableColumn { header: column::KEY_CODE     , normal_width: 20, extended_width: 37, use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::MODIFIERS    , normal_width: 11, extended_width: 11, use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::KEY          , normal_width: 25, extended_width: 42, use_extended_width: true , enabled: true , });
+    table.add_column(TableColumn { header: column::TEXT         , normal_width: 12, extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::KEY_NO_MOD   , normal_width: 25, extended_width: 42, use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::TEXT_ALL_MODS, normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{record::KeyEventRecord, Row};
+
+    struct RowCollector(Vec<Row>);
+
+    impl TablePrinter for RowCollector {
+        fn begin_new_table(&mut self, _: &Table) {}
+
+        fn print_row(&mut self, _: &Table, row: &Row) {
+            self.0.push(row.clone());
+        }
+
+        fn update_row(&mut self, _: &Table, row: &Row) {
+            self.0.push(row.clone());
+        }
+    }
+
+    #[test]
+    fn keys_are_asked_for_under_each_combination() {
+        let mut checklist = Checklist::new(PhysicalLayout::Iso);
+        let key_count = checklist.keys.len();
+        assert!(checklist
+            .keys
+            .iter()
+            .any(|key| key.key_code == KeyCode::IntlBackslash));
+        assert!(!checklist
+            .keys
+            .iter()
+            .any(|key| key.key_code == KeyCode::ShiftLeft));
+
+        let press = |physical_key, text| {
+            KeyEventRecord::for_test(physical_key, ElementState::Pressed)
+                .with_text(text)
+                .into_event()
+        };
+
+        // Escape comes first. Other keys don't count, and table control skips.
+        assert!(checklist
+            .prompt()
+            .unwrap()
+            .starts_with("Press Esc (Escape) with no modifiers"));
+        assert!(!checklist.handle_event(&press(KeyCode::KeyA, "a")));
+        assert!(checklist.handle_event(&press(KeyCode::Escape, "\u{1b}")));
+        assert!(checklist.handle_event(&RecordedEvent::TableControl));
+
+        checklist.position = key_count;
+        assert!(checklist
+            .prompt()
+            .unwrap()
+            .contains("(Escape) with Shift held"));
+        assert!(!checklist.handle_event(&press(KeyCode::Escape, "\u{1b}")));
+        checklist.handle_event(&press(KeyCode::ShiftRight, ""));
+        assert!(checklist.handle_event(&press(KeyCode::Escape, "\u{1b}")));
+
+        checklist.position = checklist.len();
+        assert!(checklist.is_finished());
+        assert_eq!(checklist.prompt(), None);
+
+        let mut rows = RowCollector(Vec::new());
+        checklist.print(&mut rows);
+        let rows = rows.0;
+        assert_eq!(rows.len(), checklist.len());
+        assert_eq!(rows[0].get(column::TEXT), Some("\"\\u{1b}\""));
+        assert_eq!(rows[1].get(column::KEY_CODE), Some("Escape"));
+        assert_eq!(rows[1].get(column::MODIFIERS), Some("Shift"));
+        assert!(rows[1].get(column::KEY).is_some());
+        assert_eq!(rows[2].get(column::MODIFIERS), Some("AltGr"));
+        assert_eq!(rows[2].get(column::KEY), None);
+        // F1 was skipped.
+        assert_eq!(rows[4].get(column::KEY_CODE), Some("F1"));
+        assert_eq!(rows[4].get(column::KEY), None);
+    }
+}
@@ -0,0 +1,333 @@
+//! Keyboard chords that trigger the same actions as the mouse buttons.
+//!
+//! A chord is pressed by holding its other keys and pressing its last key.
+//! The events of that last key are taken out of the stream until it's
+//! released, so they don't show up in the table or the recording. The presses
+//! of the other keys do, since they aren't known to be part of a chord when
+//! they are pressed. For the table control chord, their releases are taken
+//! out as well, so that they don't begin a table of their own. Everything that
+//! keeps track of held keys forgets them on the table control button.
+//!
+//! Device events of a key usually arrive before its window event, so a chord
+//! is recognized by whichever comes first. Device events without focus are
+//! ignored, since the keys are pressed for another window then. Not every
+//! platform has both kinds of events. A chord that is recognized by a device
+//! event takes out the window events of its key unless the key is released
+//! before its window press arrives, and one that is recognized by a window
+//! event only takes out device events if there have been any.
+
+use std::collections::HashSet;
+
+use winit::{event::ElementState, keyboard::KeyCode};
+
+use crate::{config::KeyBindings, record::RecordedEvent};
+
+/// Physical keys like `ControlRight+Enter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chord {
+    /// The keys that have to be held.
+    pub held: Vec<KeyCode>,
+    /// The key that triggers the chord.
+    pub key: KeyCode,
+}
+
+pub struct ChordDetector {
+    chords: Vec<(Chord, RecordedEvent)>,
+    focused: bool,
+    held: HashSet<KeyCode>,
+    /// Keys that triggered a chord, and the held keys of a table control
+    /// chord, until their window and device releases.
+    window_swallowed: HashSet<KeyCode>,
+    device_swallowed: HashSet<KeyCode>,
+    /// Keys that triggered a chord with a device event, until their window
+    /// press arrives.
+    window_press_pending: HashSet<KeyCode>,
+    window_events_seen: bool,
+    device_events_seen: bool,
+}
+
+impl ChordDetector {
+    pub fn new(bindings: &KeyBindings) -> Self {
+        let chords = [
+            (&bindings.table_control, RecordedEvent::TableControl),
+            (&bindings.dead_key_reset, RecordedEvent::DeadKeyReset),
+            (&bindings.ime_toggle, RecordedEvent::ImeToggle),
+            (&bindings.ime_position, RecordedEvent::ImePosition),
+            (&bindings.repeat_mode, RecordedEvent::RepeatModeCycle),
+        ]
+        .iter()
+        .filter_map(|(chord, action)| Some(((*chord).clone()?, action.clone())))
+        .collect();
+        Self {
+            chords,
+            focused: true,
+            held: HashSet::new(),
+            window_swallowed: HashSet::new(),
+            device_swallowed: HashSet::new(),
+            window_press_pending: HashSet::new(),
+            window_events_seen: false,
+            device_events_seen: false,
+        }
+    }
+
+    /// Returns the action of the chord that `event` completes, nothing for
+    /// the other events of its key, or the event itself.
+    pub fn handle_event(&mut self, event: RecordedEvent) -> Option<RecordedEvent> {
+        let (key_code, state, device) = match &event {
+            RecordedEvent::KeyboardInput { event, .. } => (event.physical_key, event.state, false),
+            RecordedEvent::DeviceKey {
+                physical_key,
+                state,
+                ..
+            } if self.focused => (*physical_key, *state, true),
+            RecordedEvent::Focused { focused } => {
+                self.focused = *focused;
+                // Releases aren't seen without focus.
+                if !focused {
+                    self.held.clear();
+                    self.window_swallowed.clear();
+                    self.device_swallowed.clear();
+                    self.window_press_pending.clear();
+                }
+                return Some(event);
+            }
+            _ => return Some(event),
+        };
+        if device {
+            self.device_events_seen = true;
+            // Without a window press, the platform doesn't send window events
+            // for the key.
+            if state == ElementState::Released && self.window_press_pending.remove(&key_code) {
+                self.window_swallowed.remove(&key_code);
+            }
+        } else {
+            self.window_events_seen = true;
+            self.window_press_pending.remove(&key_code);
+        }
+
+        let swallowed = if device {
+            &mut self.device_swallowed
+        } else {
+            &mut self.window_swallowed
+        };
+        if swallowed.contains(&key_code) {
+            if state == ElementState::Released {
+                swallowed.remove(&key_code);
+                self.held.remove(&key_code);
+            }
+            return None;
+        }
+
+        match state {
+            ElementState::Pressed => {
+                let held = &self.held;
+                let chord = self.chords.iter().find(|(chord, _)| {
+                    chord.key == key_code && chord.held.iter().all(|key| held.contains(key))
+                });
+                if let Some((chord, action)) = chord {
+                    if let RecordedEvent::TableControl = action {
+                        for key in &chord.held {
+                            if self.window_events_seen {
+                                self.window_swallowed.insert(*key);
+                            }
+                            if self.device_events_seen {
+                                self.device_swallowed.insert(*key);
+                            }
+                        }
+                    }
+                    if device {
+                        self.device_swallowed.insert(key_code);
+                        self.window_swallowed.insert(key_code);
+                        self.window_press_pending.insert(key_code);
+                    } else {
+                        self.window_swallowed.insert(key_code);
+                        if self.device_events_seen {
+                            self.device_swallowed.insert(key_code);
+                        }
+                    }
+                    return Some(action.clone());
+                }
+                self.held.insert(key_code);
+            }
+            ElementState::Released => {
+                self.held.remove(&key_code);
+            }
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{device_key, key};
+
+    /// Handles events with `ControlRight+Enter` bound to the table control
+    /// button, and returns what came out.
+    fn handler() -> impl FnMut(RecordedEvent) -> &'static str {
+        handler_for(Chord {
+            held: vec![KeyCode::ControlRight],
+            key: KeyCode::Enter,
+        })
+    }
+
+    fn handler_for(table_control: Chord) -> impl FnMut(RecordedEvent) -> &'static str {
+        let mut detector = ChordDetector::new(&KeyBindings {
+            table_control: Some(table_control),
+            ..KeyBindings::default()
+        });
+        move |event| match detector.handle_event(event) {
+            None => "-",
+            Some(RecordedEvent::TableControl) => "TableControl",
+            Some(_) => "Event",
+        }
+    }
+
+    #[test]
+    fn chords_work_with_one_kind_of_event() {
+        let window_key = |key_code, state| key(key_code, state, false);
+        for make_event in [window_key, device_key].iter() {
+            let mut handle = handler();
+            let mut handle_key = |key_code, state| handle(make_event(key_code, state));
+
+            assert_eq!(
+                handle_key(KeyCode::ControlRight, ElementState::Pressed),
+                "Event"
+            );
+            assert_eq!(
+                handle_key(KeyCode::Enter, ElementState::Pressed),
+                "TableControl"
+            );
+            assert_eq!(handle_key(KeyCode::Enter, ElementState::Released), "-");
+            assert_eq!(
+                handle_key(KeyCode::ControlRight, ElementState::Released),
+                "-"
+            );
+            // The next press of the key isn't taken for the other kind of
+            // event.
+            assert_eq!(handle_key(KeyCode::Enter, ElementState::Pressed), "Event");
+            assert_eq!(handle_key(KeyCode::Enter, ElementState::Released), "Event");
+        }
+    }
+
+    #[test]
+    fn chords_without_held_keys_fire_once() {
+        let mut handle = handler_for(Chord {
+            held: Vec::new(),
+            key: KeyCode::Backspace,
+        });
+
+        for _ in 0..2 {
+            assert_eq!(
+                handle(device_key(KeyCode::Backspace, ElementState::Pressed)),
+                "TableControl"
+            );
+            assert_eq!(
+                handle(key(KeyCode::Backspace, ElementState::Pressed, false)),
+                "-"
+            );
+            assert_eq!(
+                handle(device_key(KeyCode::Backspace, ElementState::Released)),
+                "-"
+            );
+            assert_eq!(
+                handle(key(KeyCode::Backspace, ElementState::Released, false)),
+                "-"
+            );
+        }
+    }
+
+    #[test]
+    fn chords_are_ignored_without_focus() {
+        let mut handle = handler();
+
+        assert_eq!(handle(RecordedEvent::Focused { focused: false }), "Event");
+        assert_eq!(
+            handle(device_key(KeyCode::ControlRight, ElementState::Pressed)),
+            "Event"
+        );
+        assert_eq!(
+            handle(device_key(KeyCode::Enter, ElementState::Pressed)),
+            "Event"
+        );
+        assert_eq!(
+            handle(device_key(KeyCode::Enter, ElementState::Released)),
+            "Event"
+        );
+    }
+
+    #[test]
+    fn chords_replace_the_events_of_their_key() {
+        let mut handle = handler();
+
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Pressed, false)),
+            "Event"
+        );
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Released, false)),
+            "Event"
+        );
+
+        assert_eq!(
+            handle(device_key(KeyCode::ControlRight, ElementState::Pressed)),
+            "Event"
+        );
+        assert_eq!(
+            handle(key(KeyCode::ControlRight, ElementState::Pressed, false)),
+            "Event"
+        );
+        assert_eq!(
+            handle(device_key(KeyCode::Enter, ElementState::Pressed)),
+            "TableControl"
+        );
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Pressed, false)),
+            "-"
+        );
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Pressed, true)),
+            "-"
+        );
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Released, false)),
+            "-"
+        );
+        assert_eq!(
+            handle(device_key(KeyCode::Enter, ElementState::Released)),
+            "-"
+        );
+
+        // Without device events, the window event triggers the chord.
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Pressed, false)),
+            "TableControl"
+        );
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Released, false)),
+            "-"
+        );
+
+        // The held key is taken out until both of its releases.
+        assert_eq!(
+            handle(key(KeyCode::ControlRight, ElementState::Pressed, true)),
+            "-"
+        );
+        assert_eq!(
+            handle(device_key(KeyCode::ControlRight, ElementState::Released)),
+            "-"
+        );
+        assert_eq!(
+            handle(key(KeyCode::ControlRight, ElementState::Released, false)),
+            "-"
+        );
+        assert_eq!(
+            handle(key(KeyCode::ControlRight, ElementState::Pressed, false)),
+            "Event"
+        );
+        assert_eq!(
+            handle(key(KeyCode::Enter, ElementState::Pressed, false)),
+            "TableControl"
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use clap::{Args, Parser, ValueEnum};
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+use crate::{
+    column,
+    layout::PhysicalLayout,
+    session::{RepeatMode, SplitPolicy},
+    Table,
+};
+
+/// Prints Winit's keyboard events as markdown tables.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Options {
+    /// Record every event to a JSON Lines session file.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Print the tables for a recorded session instead of opening a window.
+    #[arg(long, value_name = "FILE", conflicts_with = "record")]
+    pub replay: Option<PathBuf>,
+
+    /// Read the configuration from this file instead of the default location.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// When to finish a table: `timeout`, `idle`, `focus`, `events:<N>` or
+    /// `never` [default: timeout]
+    ///
+    /// `timeout` finishes the table once all keys have been released and the
+    /// table timeout passed without events. `idle` begins a new table when an
+    /// event arrives after a gap of at least the table timeout, even if keys
+    /// are still being held. `focus` finishes the table when the window gains
+    /// or loses focus, and `events:<N>` after N rows. With `never`, tables are
+    /// only finished with the middle mouse button.
+    #[arg(long, value_name = "POLICY")]
+    pub split: Option<SplitPolicy>,
+
+    /// The table timeout in seconds, used by the `timeout` and `idle` split
+    /// policies [default: 5]
+    #[arg(long, value_name = "SECONDS", value_parser = parse_table_timeout)]
+    pub table_timeout: Option<Duration>,
+
+    /// Start in manual mode.
+    #[arg(long)]
+    pub manual: bool,
+
+    /// Show the modifiers in the `Modifiers` column of window and device rows
+    /// too, not only of `ModC` rows.
+    ///
+    /// Modifiers that changed since the previous window or device row are
+    /// prefixed with `*`.
+    #[arg(long)]
+    pub key_modifiers: bool,
+
+    /// How repeated key events are shown [default: collapsed]
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub repeats: Option<RepeatMode>,
+
+    /// The format of the printed tables.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// The keyboard drawn into the window [default: ansi]
+    #[arg(long, value_enum, value_name = "LAYOUT")]
+    pub keyboard: Option<PhysicalLayout>,
+
+    /// Ask for every key of LAYOUT in turn, with and without Shift and AltGr,
+    /// and print a table of what each of them produced.
+    ///
+    /// The table control button skips a key. The table is printed once every
+    /// key has been asked for, or when the window is closed.
+    #[arg(long, value_enum, value_name = "LAYOUT")]
+    pub checklist: Option<PhysicalLayout>,
+
+    /// Check key presses against a reference mapping, as printed by
+    /// `--checklist` with `--format json`.
+    ///
+    /// Presses that don't match are marked in the `Mismatch` column, and every
+    /// table ends with a summary.
+    #[arg(long, value_name = "FILE")]
+    pub expect: Option<PathBuf>,
+
+    /// Flag events that don't add up, like releases without a press or keys
+    /// that are held across focus loss, in the `Anomaly` column.
+    ///
+    /// Every table ends with a summary, which also lists the keys that the
+    /// window and device events disagree on.
+    #[arg(long)]
+    pub anomalies: bool,
+
+    /// Pair device events with the window events they produced, in the
+    /// `Pair` column.
+    ///
+    /// The later event of a pair shows the row of the earlier one and how
+    /// much later it arrived. Every table ends with a summary of the latencies
+    /// and the events that didn't get a pair.
+    #[arg(long)]
+    pub pair: bool,
+
+    /// Check the modifiers that winit reports against the modifier keys that
+    /// are held, in the `Modifier check` column.
+    ///
+    /// Key events are flagged when a modifier is missing or spurious, and
+    /// `ModifiersChanged` events when they only fix that after another key.
+    /// Every table ends with a summary.
+    #[arg(long)]
+    pub check_modifiers: bool,
+
+    /// Show when each event arrived in the `Time` and `Δ` columns, and end
+    /// every table with how long each key was held and how it repeated.
+    ///
+    /// `Time` is relative to the first row of the table and `Δ` is the time
+    /// since the previous row. Both can also be shown on their own.
+    #[arg(long)]
+    pub timing: bool,
+
+    /// Show IME events in the `Preedit`, `Commit` and `Composed` columns.
+    ///
+    /// The preedit is shown with brackets around the selected text, or `[]`
+    /// where the cursor is. `Composed` is all text committed in the table so
+    /// far.
+    #[arg(long)]
+    pub ime: bool,
+
+    /// Group dead keys and compose sequences with the keys that follow them,
+    /// in the `Dead keys` column.
+    ///
+    /// Sequences that end without text, or that are still composed after the
+    /// dead key reset button, are flagged. Every table ends with a summary.
+    #[arg(long)]
+    pub dead_keys: bool,
+
+    #[command(flatten)]
+    pub column_options: ColumnOptions,
+}
+
+/// Columns are named by their header or the name of their constant, ignoring
+/// case. For example, both `KeyCode` and `key_code` work.
+///
+/// The same options can be given in the `[table]` section of the config file.
+#[derive(Args, Deserialize, Debug, Default)]
+#[command(next_help_heading = "Columns")]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnOptions {
+    /// Only show these columns, in this order
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = parse_column)]
+    #[serde(deserialize_with = "deserialize_columns")]
+    pub columns: Vec<&'static str>,
+
+    /// Show these columns, even if they are hidden by default
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = parse_column)]
+    #[serde(deserialize_with = "deserialize_columns")]
+    pub show: Vec<&'static str>,
+
+    /// Hide these columns
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = parse_column)]
+    #[serde(deserialize_with = "deserialize_columns")]
+    pub hide: Vec<&'static str>,
+
+    /// Use the extended width for these columns
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = parse_column)]
+    #[serde(deserialize_with = "deserialize_columns")]
+    pub wide: Vec<&'static str>,
+
+    /// Use the normal width for these columns
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = parse_column)]
+    #[serde(deserialize_with = "deserialize_columns")]
+    pub narrow: Vec<&'static str>,
+
+    /// Set the width of a column, e.g. `Key=30`
+    #[arg(long, value_name = "COLUMN=WIDTH", value_parser = parse_column_width)]
+    #[serde(deserialize_with = "deserialize_column_widths")]
+    pub width: Vec<(&'static str, usize)>,
+}
+
+impl ColumnOptions {
+    pub fn apply(&self, table: &mut Table) {
+        if !self.columns.is_empty() {
+            table.select_columns(&self.columns);
+        }
+        for header in &self.show {
+            if let Some(column) = table.column_mut(header) {
+                column.enabled = true;
+            }
+        }
+        for header in &self.hide {
+            if let Some(column) = table.column_mut(header) {
+                column.enabled = false;
+            }
+        }
+        for header in &self.wide {
+            if let Some(column) = table.column_mut(header) {
+                column.use_extended_width = true;
+            }
+        }
+        for header in &self.narrow {
+            if let Some(column) = table.column_mut(header) {
+                column.use_extended_width = false;
+            }
+        }
+        for (header, width) in &self.width {
+            if let Some(column) = table.column_mut(header) {
+                column.normal_width = *width;
+                column.extended_width = *width;
+            }
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Markdown tables that can be pasted into GitHub comments
+    #[default]
+    Markdown,
+    /// Comma separated values, for spreadsheets
+    Csv,
+    /// Tab separated values, for spreadsheets
+    Tsv,
+    /// One JSON object per row, with typed values
+    Json,
+}
+
+fn parse_column(name: &str) -> Result<&'static str, String> {
+    column::from_name(name).ok_or_else(|| {
+        let names: Vec<_> = column::ALL.iter().map(|(_, header)| *header).collect();
+        format!("expected one of: {}", names.join(", "))
+    })
+}
+
+fn parse_column_width(arg: &str) -> Result<(&'static str, usize), String> {
+    let (name, width) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected COLUMN=WIDTH".to_string())?;
+    let width = width
+        .parse()
+        .map_err(|err| format!("invalid width {:?}: {}", width, err))?;
+    Ok((parse_column(name)?, width))
+}
+
+fn parse_table_timeout(arg: &str) -> Result<Duration, String> {
+    let secs = arg
+        .parse()
+        .map_err(|err| format!("invalid number of seconds {:?}: {}", arg, err))?;
+    table_timeout_from_secs(secs)
+}
+
+/// Checks that a table timeout is a duration, which rules out negative,
+/// infinite and NaN values.
+pub fn table_timeout_from_secs(secs: f64) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(secs).map_err(|_| {
+        format!(
+            "invalid table timeout {}, expected a non-negative number of seconds",
+            secs
+        )
+    })
+}
+
+impl Options {
+    pub fn from_args() -> Self {
+        Self::parse()
+    }
+}
+
+fn deserialize_columns<'de, D>(deserializer: D) -> Result<Vec<&'static str>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|name| parse_column(name).map_err(D::Error::custom))
+        .collect()
+}
+
+fn deserialize_column_widths<'de, D>(
+    deserializer: D,
+) -> Result<Vec<(&'static str, usize)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, usize>::deserialize(deserializer)?
+        .iter()
+        .map(|(name, width)| Ok((parse_column(name).map_err(D::Error::custom)?, *width)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_table;
+
+    fn enabled(table: &Table) -> Vec<&'static str> {
+        table
+            .columns
+            .iter()
+            .filter(|column| column.enabled)
+            .map(|column| column.header)
+            .collect()
+    }
+
+    #[test]
+    fn columns_are_parsed_by_header_or_constant() {
+        assert_eq!(parse_column("KeyCode"), Ok(column::KEY_CODE));
+        assert_eq!(parse_column("key_code"), Ok(column::KEY_CODE));
+        assert_eq!(parse_column("keycode"), Ok(column::KEY_CODE));
+        assert_eq!(
+            parse_column("text (all modifiers)"),
+            Ok(column::TEXT_ALL_MODS)
+        );
+        assert!(parse_column("Nope")
+            .unwrap_err()
+            .starts_with("expected one of: Number, "));
+    }
+
+    #[test]
+    fn column_widths_are_parsed() {
+        assert_eq!(parse_column_width("Key=30"), Ok((column::KEY, 30)));
+        assert_eq!(
+            parse_column_width("Key"),
+            Err("expected COLUMN=WIDTH".to_string())
+        );
+        assert!(parse_column_width("Key=wide")
+            .unwrap_err()
+            .starts_with("invalid width \"wide\""));
+        assert!(parse_column_width("Key=-1").is_err());
+        assert!(parse_column_width("Nope=3")
+            .unwrap_err()
+            .starts_with("expected one of"));
+    }
+
+    #[test]
+    fn table_timeouts_are_checked() {
+        assert_eq!(parse_table_timeout("2.5"), Ok(Duration::from_millis(2500)));
+        assert_eq!(parse_table_timeout("0"), Ok(Duration::ZERO));
+        for arg in ["-1", "NaN", "inf", "1e300", "soon"].iter() {
+            assert!(parse_table_timeout(arg).is_err(), "{}", arg);
+        }
+    }
+
+    #[test]
+    fn column_options_are_applied_in_order() {
+        let mut table = build_table();
+        ColumnOptions {
+            columns: vec![column::KEY, column::KIND, column::SYNTH, column::TEXT],
+            show: vec![column::SYNTH, column::TEXT],
+            // Hiding wins over showing, and narrow over wide.
+            hide: vec![column::SYNTH],
+            wide: vec![column::KIND, column::TEXT],
+            narrow: vec![column::KIND],
+            width: vec![(column::TEXT, 7)],
+        }
+        .apply(&mut table);
+
+        assert_eq!(enabled(&table), [column::KEY, column::KIND, column::TEXT]);
+        let kind = table.column_mut(column::KIND).unwrap();
+        assert!(!kind.use_extended_width);
+        let text = table.column_mut(column::TEXT).unwrap();
+        assert!(text.use_extended_width);
+        assert_eq!((text.normal_width, text.extended_width), (7, 7));
+    }
+
+    #[test]
+    fn command_line_columns_override_the_config() {
+        let mut table = build_table();
+        let config = ColumnOptions {
+            columns: vec![column::KIND, column::KEY],
+            ..ColumnOptions::default()
+        };
+        let command_line = ColumnOptions {
+            show: vec![column::NUMBER],
+            ..ColumnOptions::default()
+        };
+        config.apply(&mut table);
+        command_line.apply(&mut table);
+        assert_eq!(enabled(&table), [column::KIND, column::KEY, column::NUMBER]);
+
+        let command_line = ColumnOptions {
+            columns: vec![column::STATE, column::KIND],
+            ..ColumnOptions::default()
+        };
+        command_line.apply(&mut table);
+        assert_eq!(enabled(&table), [column::STATE, column::KIND]);
+    }
+}
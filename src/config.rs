@@ -0,0 +1,326 @@
+//! The configuration file.
+//!
+//! The config file is a TOML file that is read from the first of these places:
+//!
+//! 1. The path given with `--config`.
+//! 2. The path in the `WINIT_KEYBOARD_TESTER_CONFIG` environment variable.
+//! 3. `winit_keyboard_tester/config.toml` in the user's config directory,
+//!    which is `$XDG_CONFIG_HOME`, `%APPDATA%` or `~/.config`.
+//!
+//! Options given on the command line take precedence over the config file.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{
+    de::{value::StrDeserializer, Error as _, IntoDeserializer},
+    Deserialize, Deserializer,
+};
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{
+    chords::Chord,
+    cli::{table_timeout_from_secs, ColumnOptions, OutputFormat},
+    layout::PhysicalLayout,
+    record::RecordedEvent,
+    session::{RepeatMode, SplitPolicy},
+};
+
+pub const CONFIG_ENV_VAR: &str = "WINIT_KEYBOARD_TESTER_CONFIG";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window_title: Option<String>,
+    /// When to finish a table. See `--split`.
+    pub split: Option<SplitPolicy>,
+    /// Seconds without events after which the current table is finished.
+    #[serde(deserialize_with = "deserialize_table_timeout")]
+    pub table_timeout: Option<Duration>,
+    /// Start in manual mode.
+    pub manual_mode: bool,
+    /// How repeated key events are shown. See `--repeats`.
+    pub repeats: Option<RepeatMode>,
+    pub format: Option<OutputFormat>,
+    /// The keyboard drawn into the window.
+    pub keyboard: Option<PhysicalLayout>,
+    pub table: ColumnOptions,
+    pub mouse: MouseBindings,
+    pub keys: KeyBindings,
+    pub ime: ImeSettings,
+}
+
+impl Config {
+    /// Loads the config file from `path`, or from the default location if
+    /// there is no `path`.
+    ///
+    /// It's fine for there to be no config file at the default location.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match env::var_os(CONFIG_ENV_VAR) {
+                Some(path) => (PathBuf::from(path), true),
+                None => match default_path() {
+                    Some(path) => (path, false),
+                    None => return Ok(Self::default()),
+                },
+            },
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default())
+            }
+            Err(err) => return Err(format!("Failed to read {}: {}", path.display(), err)),
+        };
+
+        toml::from_str(&text)
+            .map_err(|err| format!("Invalid config in {}: {}", path.display(), err))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(target_os = "windows") {
+                env::var_os("APPDATA").map(PathBuf::from)
+            } else {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+            }
+        })?;
+
+    Some(config_dir.join("winit_keyboard_tester").join("config.toml"))
+}
+
+/// Which mouse buttons trigger the tester's actions.
+///
+/// Buttons are named `"left"`, `"right"`, `"middle"` or `"none"`. Other
+/// buttons are given by their number.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MouseBindings {
+    /// Finishes the current table, or toggles manual mode when it's empty.
+    #[serde(deserialize_with = "deserialize_mouse_button")]
+    pub table_control: Option<MouseButton>,
+    #[serde(deserialize_with = "deserialize_mouse_button")]
+    pub dead_key_reset: Option<MouseButton>,
+    /// Allows or disallows IME input.
+    #[serde(deserialize_with = "deserialize_mouse_button")]
+    pub ime_toggle: Option<MouseButton>,
+    /// Moves the IME to [`ImeSettings::position`].
+    #[serde(deserialize_with = "deserialize_mouse_button")]
+    pub ime_position: Option<MouseButton>,
+    /// Switches between the repeat modes.
+    #[serde(deserialize_with = "deserialize_mouse_button")]
+    pub repeat_mode: Option<MouseButton>,
+}
+
+impl Default for MouseBindings {
+    fn default() -> Self {
+        Self {
+            table_control: Some(MouseButton::Middle),
+            dead_key_reset: Some(MouseButton::Right),
+            ime_toggle: None,
+            ime_position: None,
+            repeat_mode: None,
+        }
+    }
+}
+
+impl MouseBindings {
+    pub fn action(&self, button: MouseButton) -> Option<RecordedEvent> {
+        if self.table_control == Some(button) {
+            Some(RecordedEvent::TableControl)
+        } else if self.dead_key_reset == Some(button) {
+            Some(RecordedEvent::DeadKeyReset)
+        } else if self.ime_toggle == Some(button) {
+            Some(RecordedEvent::ImeToggle)
+        } else if self.ime_position == Some(button) {
+            Some(RecordedEvent::ImePosition)
+        } else if self.repeat_mode == Some(button) {
+            Some(RecordedEvent::RepeatModeCycle)
+        } else {
+            None
+        }
+    }
+}
+
+/// Which keyboard chords trigger the tester's actions, as an alternative to
+/// the mouse buttons.
+///
+/// Chords are physical keys joined with `+`, like `"ControlRight+Enter"`, or
+/// `"none"`. None of them are bound by default.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    #[serde(deserialize_with = "deserialize_chord")]
+    pub table_control: Option<Chord>,
+    #[serde(deserialize_with = "deserialize_chord")]
+    pub dead_key_reset: Option<Chord>,
+    #[serde(deserialize_with = "deserialize_chord")]
+    pub ime_toggle: Option<Chord>,
+    #[serde(deserialize_with = "deserialize_chord")]
+    pub ime_position: Option<Chord>,
+    #[serde(deserialize_with = "deserialize_chord")]
+    pub repeat_mode: Option<Chord>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImeSettings {
+    /// Allow IME input from the start.
+    pub allowed: bool,
+    /// Where the IME places its candidate window, in logical pixels from the
+    /// top left corner of the window.
+    pub position: [f64; 2],
+}
+
+impl Default for ImeSettings {
+    fn default() -> Self {
+        Self {
+            allowed: false,
+            position: [20.0, 20.0],
+        }
+    }
+}
+
+fn deserialize_table_timeout<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secs = f64::deserialize(deserializer)?;
+    table_timeout_from_secs(secs)
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+fn deserialize_mouse_button<'de, D>(deserializer: D) -> Result<Option<MouseButton>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Button {
+        Name(String),
+        Number(u16),
+    }
+
+    match Button::deserialize(deserializer)? {
+        Button::Number(number) => Ok(Some(MouseButton::Other(number))),
+        Button::Name(name) => match name.to_ascii_lowercase().as_str() {
+            "left" => Ok(Some(MouseButton::Left)),
+            "right" => Ok(Some(MouseButton::Right)),
+            "middle" => Ok(Some(MouseButton::Middle)),
+            "none" => Ok(None),
+            _ => Err(D::Error::custom(format!(
+                "unknown mouse button {:?}, expected \"left\", \"right\", \"middle\", \"none\" or a number",
+                name
+            ))),
+        },
+    }
+}
+
+fn deserialize_chord<'de, D>(deserializer: D) -> Result<Option<Chord>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    if text.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    let mut keys = text
+        .split('+')
+        .map(|name| {
+            let name: StrDeserializer<'_, D::Error> = name.trim().into_deserializer();
+            KeyCode::deserialize(name).map_err(|_| {
+                D::Error::custom(format!(
+                    "unknown key in chord {:?}, expected key codes like \"ControlRight+Enter\"",
+                    text
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = keys.pop().expect("split returns at least one part");
+    Ok(Some(Chord { held: keys, key }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        toml::from_str::<Config>(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn mouse_buttons_are_parsed_by_name_or_number() {
+        let config: Config = toml::from_str(
+            r#"
+            [mouse]
+            table_control = 4
+            dead_key_reset = "none"
+            ime_toggle = "Left"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.mouse.table_control, Some(MouseButton::Other(4)));
+        assert_eq!(config.mouse.dead_key_reset, None);
+        assert_eq!(config.mouse.ime_toggle, Some(MouseButton::Left));
+        assert_eq!(config.mouse.ime_position, None);
+
+        let message = error("mouse = { table_control = \"wheel\" }");
+        assert!(
+            message.contains("unknown mouse button \"wheel\""),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn chords_are_parsed() {
+        let config: Config = toml::from_str(
+            r#"
+            [keys]
+            table_control = "ControlRight + ShiftRight + Enter"
+            dead_key_reset = "Backspace"
+            ime_toggle = "none"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.keys.table_control,
+            Some(Chord {
+                held: vec![KeyCode::ControlRight, KeyCode::ShiftRight],
+                key: KeyCode::Enter,
+            })
+        );
+        assert_eq!(
+            config.keys.dead_key_reset,
+            Some(Chord {
+                held: Vec::new(),
+                key: KeyCode::Backspace,
+            })
+        );
+        assert_eq!(config.keys.ime_toggle, None);
+
+        let message = error("keys = { table_control = \"ControlRight+Return\" }");
+        assert!(
+            message.contains("unknown key in chord \"ControlRight+Return\""),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(error("manual = true").contains("unknown field `manual`"));
+        assert!(error("[mouse]\nmiddle = \"table_control\"").contains("unknown field `middle`"));
+        assert!(error("[keys]\ntable_contrl = \"Enter\"").contains("unknown field `table_contrl`"));
+    }
+}
@@ -0,0 +1,313 @@
+//! Tracking of dead key and compose sequences.
+//!
+//! A sequence begins with the press of a dead key or the compose key and ends
+//! with the first press that produces text. Winit reports the key itself as
+//! the logical key of that press, and the composed character as its text.
+//! Modifiers don't end a sequence, and neither do the keys of a compose
+//! sequence, which don't produce text until the sequence is complete.
+//!
+//! A sequence that is reset with the dead key reset button should be gone
+//! for good. If the next press that produces text is still composed, the
+//! sequence leaked across the reset.
+
+use serde_json::json;
+use winit::{event::ElementState, keyboard::Key};
+
+use crate::{key_to_string, nice_text, record::RecordedEvent, Cell};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Waiting for more keys.
+    Open,
+    Composed,
+    /// Ended by a key that didn't produce any text.
+    Unresolved,
+    /// Ended by the dead key reset button.
+    Reset,
+    /// Composed by the first press after the sequence was reset.
+    Leaked,
+}
+
+impl Outcome {
+    fn is_problem(self) -> bool {
+        matches!(self, Outcome::Unresolved | Outcome::Leaked)
+    }
+}
+
+struct Sequence {
+    /// The number of the sequence in the current table, starting at 1.
+    number: usize,
+    keys: Vec<String>,
+    compose: bool,
+}
+
+pub struct DeadKeyTracker {
+    open: Option<Sequence>,
+    /// The sequence that was reset, until the next press that produces text.
+    reset: Option<Sequence>,
+    /// The outcomes of the sequences in the current table.
+    outcomes: Vec<Outcome>,
+}
+
+impl DeadKeyTracker {
+    pub fn new() -> Self {
+        Self {
+            open: None,
+            reset: None,
+            outcomes: Vec::new(),
+        }
+    }
+
+    /// Returns the sequence that the event belongs to, along with whether
+    /// something went wrong with it.
+    pub fn handle_event(&mut self, event: &RecordedEvent) -> Option<(Cell, bool)> {
+        let event = match event {
+            RecordedEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed && !event.repeat =>
+            {
+                event
+            }
+            RecordedEvent::DeadKeyReset => {
+                let sequence = self.open.take()?;
+                let cell = self.finish(&sequence, None, Outcome::Reset);
+                self.reset = Some(sequence);
+                return Some(cell);
+            }
+            // The sequence may have been finished in another window.
+            RecordedEvent::Focused { focused: false } => {
+                self.open = None;
+                self.reset = None;
+                return None;
+            }
+            _ => return None,
+        };
+        let logical_key = &event.logical_key;
+        let starts_sequence = matches!(logical_key, Key::Dead(_) | Key::Compose);
+
+        if let Some(text) = &event.text {
+            if let Some(mut sequence) = self.open.take() {
+                sequence.keys.push(key_label(logical_key));
+                return Some(self.finish(&sequence, Some(text), Outcome::Composed));
+            }
+            let composed = match logical_key {
+                Key::Character(character) => character.as_str() != text,
+                _ => false,
+            };
+            let sequence = self.reset.take()?;
+            if !composed || text.chars().any(char::is_control) {
+                return None;
+            }
+            return Some(self.finish(&sequence, Some(text), Outcome::Leaked));
+        }
+
+        match &mut self.open {
+            None if starts_sequence => {
+                let sequence = Sequence {
+                    number: self.outcomes.len() + 1,
+                    keys: vec![key_label(logical_key)],
+                    compose: *logical_key == Key::Compose,
+                };
+                self.outcomes.push(Outcome::Open);
+                let cell = describe(&sequence, None, Outcome::Open);
+                self.open = Some(sequence);
+                Some(cell)
+            }
+            None => None,
+            Some(_) if is_modifier(logical_key) => None,
+            Some(sequence)
+                if starts_sequence
+                    || (sequence.compose && matches!(logical_key, Key::Character(_))) =>
+            {
+                sequence.keys.push(key_label(logical_key));
+                Some(describe(sequence, None, Outcome::Open))
+            }
+            Some(_) => {
+                let mut sequence = self.open.take()?;
+                sequence.keys.push(key_label(logical_key));
+                Some(self.finish(&sequence, None, Outcome::Unresolved))
+            }
+        }
+    }
+
+    fn finish(
+        &mut self,
+        sequence: &Sequence,
+        text: Option<&str>,
+        outcome: Outcome,
+    ) -> (Cell, bool) {
+        match outcome {
+            // A leak is counted on top of the reset of the same sequence.
+            Outcome::Leaked => self.outcomes.push(outcome),
+            _ => {
+                if let Some(last) = self.outcomes.get_mut(sequence.number - 1) {
+                    *last = outcome;
+                }
+            }
+        }
+        describe(sequence, text, outcome)
+    }
+
+    /// Sums up the sequences since the last summary, along with the number of
+    /// those that went wrong.
+    pub fn summary(&mut self) -> (Cell, usize) {
+        let outcomes = std::mem::take(&mut self.outcomes);
+        // Sequences continue into the next table, but their numbers don't.
+        if let Some(sequence) = &mut self.open {
+            sequence.number = 1;
+            self.outcomes.push(Outcome::Open);
+        }
+        let count = |outcome| outcomes.iter().filter(|o| **o == outcome).count();
+        let leaked = count(Outcome::Leaked);
+        let sequences = outcomes.len() - leaked;
+        let problems = count(Outcome::Unresolved) + leaked;
+
+        let mut parts = Vec::new();
+        for (outcome, name) in [
+            (Outcome::Composed, "composed"),
+            (Outcome::Unresolved, "unresolved"),
+            (Outcome::Reset, "reset"),
+            (Outcome::Leaked, "leaked"),
+            (Outcome::Open, "open"),
+        ]
+        .iter()
+        {
+            if count(*outcome) > 0 {
+                parts.push(format!("{} {}", count(*outcome), name));
+            }
+        }
+        let text = match sequences {
+            0 => "No sequences".to_string(),
+            1 => format!("1 sequence: {}", parts.join(", ")),
+            _ => format!("{} sequences: {}", sequences, parts.join(", ")),
+        };
+        let value = json!({
+            "sequences": sequences,
+            "composed": count(Outcome::Composed),
+            "unresolved": count(Outcome::Unresolved),
+            "reset": count(Outcome::Reset),
+            "leaked": leaked,
+            "open": count(Outcome::Open),
+        });
+        (Cell::typed(text, &value), problems)
+    }
+}
+
+fn is_modifier(key: &Key) -> bool {
+    matches!(
+        key,
+        Key::Alt
+            | Key::AltGraph
+            | Key::CapsLock
+            | Key::Control
+            | Key::Fn
+            | Key::Meta
+            | Key::NumLock
+            | Key::Shift
+            | Key::Super
+    )
+}
+
+/// How a key is shown in a sequence, like `´` for a dead acute accent.
+fn key_label(key: &Key) -> String {
+    match key {
+        Key::Character(character) => nice_text(character),
+        Key::Dead(Some(character)) => nice_text(character.to_string()),
+        Key::Dead(None) => "Dead".to_string(),
+        _ => key_to_string(key),
+    }
+}
+
+/// Describes a sequence like `1: ´ e = é`.
+fn describe(sequence: &Sequence, text: Option<&str>, outcome: Outcome) -> (Cell, bool) {
+    let keys = sequence.keys.join(" ");
+    let text_shown = match (outcome, text) {
+        (Outcome::Composed, Some(text)) => {
+            format!("{}: {} = {}", sequence.number, keys, nice_text(text))
+        }
+        (Outcome::Unresolved, _) => format!("{}: {}, unresolved", sequence.number, keys),
+        (Outcome::Reset, _) => format!("{}: {}, reset", sequence.number, keys),
+        (Outcome::Leaked, Some(text)) => {
+            format!(
+                "{}: {} leaked into {}",
+                sequence.number,
+                keys,
+                nice_text(text)
+            )
+        }
+        _ => format!("{}: {}", sequence.number, keys),
+    };
+    let value = json!({
+        "sequence": sequence.number,
+        "keys": sequence.keys,
+        "text": text,
+        "outcome": format!("{:?}", outcome),
+    });
+    (Cell::typed(text_shown, &value), outcome.is_problem())
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::keyboard::KeyCode;
+
+    use super::*;
+    use crate::record::KeyEventRecord;
+
+    #[test]
+    fn sequences_are_grouped_and_checked() {
+        let mut tracker = DeadKeyTracker::new();
+        let press = |logical_key, text| {
+            KeyEventRecord::for_test(KeyCode::KeyA, ElementState::Pressed)
+                .with_logical_key(logical_key, text)
+                .into_event()
+        };
+        let mut track = |event| tracker.handle_event(&event).map(|(cell, _)| cell.text);
+        let acute = || Key::Dead(Some('´'));
+        let character = |c: &str| Key::Character(c.into());
+
+        assert_eq!(track(press(character("a"), Some("a"))), None);
+        assert_eq!(track(press(acute(), None)).as_deref(), Some("1: ´"));
+        assert_eq!(track(press(Key::Shift, None)), None);
+        assert_eq!(
+            track(press(character("E"), Some("É"))).as_deref(),
+            Some("1: ´ E = É")
+        );
+
+        assert_eq!(
+            track(press(Key::Compose, None)).as_deref(),
+            Some("2: Compose")
+        );
+        assert_eq!(
+            track(press(character("a"), None)).as_deref(),
+            Some("2: Compose a")
+        );
+        assert_eq!(
+            track(press(character("e"), Some("æ"))).as_deref(),
+            Some("2: Compose a e = æ")
+        );
+
+        track(press(acute(), None));
+        assert_eq!(
+            track(press(Key::ArrowLeft, None)).as_deref(),
+            Some("3: ´ ArrowLeft, unresolved")
+        );
+
+        track(press(acute(), None));
+        assert_eq!(
+            track(RecordedEvent::DeadKeyReset).as_deref(),
+            Some("4: ´, reset")
+        );
+        assert_eq!(
+            track(press(character("e"), Some("é"))).as_deref(),
+            Some("4: ´ leaked into é")
+        );
+        assert_eq!(track(press(character("e"), Some("e"))), None);
+
+        let (summary, problems) = tracker.summary();
+        assert_eq!(
+            summary.text,
+            "4 sequences: 2 composed, 1 unresolved, 1 reset, 1 leaked"
+        );
+        assert_eq!(problems, 2);
+        assert_eq!(tracker.summary().0.text, "No sequences");
+    }
+}
@@ -0,0 +1,209 @@
+//! CSV and TSV output, for importing sessions into spreadsheets.
+//!
+//! The header is only written again when the columns change, so it's usually
+//! written once. Tables are separated by an empty line, and the `Number`
+//! column starts over at zero in each of them.
+
+use std::{io::Write, marker::PhantomData};
+
+use crate::{Row, Table, TablePrinter};
+
+pub type CsvTablePrinter<W> = DelimitedTablePrinter<W, Csv>;
+pub type TsvTablePrinter<W> = DelimitedTablePrinter<W, Tsv>;
+
+/// How values are separated and escaped.
+pub trait Dialect {
+    const SEPARATOR: char;
+
+    fn push_value(line: &mut String, value: &str);
+}
+
+/// Comma separated values as described in RFC 4180.
+pub struct Csv;
+
+impl Dialect for Csv {
+    const SEPARATOR: char = ',';
+
+    fn push_value(line: &mut String, value: &str) {
+        if value.contains(&[',', '"', '\n', '\r'][..]) {
+            line.push('"');
+            line.push_str(&value.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(value);
+        }
+    }
+}
+
+/// Tab separated values, with tabs, newlines and backslashes escaped by a
+/// backslash.
+pub struct Tsv;
+
+impl Dialect for Tsv {
+    const SEPARATOR: char = '\t';
+
+    fn push_value(line: &mut String, value: &str) {
+        for c in value.chars() {
+            match c {
+                '\t' => line.push_str("\\t"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                '\\' => line.push_str("\\\\"),
+                c => line.push(c),
+            }
+        }
+    }
+}
+
+/// Prints the enabled columns of each row as a line of delimited values.
+///
+/// Like [`MarkdownTablePrinter`](crate::MarkdownTablePrinter), rows that may
+/// still be updated are held back until the next row is printed.
+pub struct DelimitedTablePrinter<W, D> {
+    out: W,
+    /// The headers of the last table.
+    headers: Vec<&'static str>,
+    pending_row: Option<String>,
+    dialect: PhantomData<D>,
+}
+
+impl<W: Write, D: Dialect> DelimitedTablePrinter<W, D> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            headers: Vec::new(),
+            pending_row: None,
+            dialect: PhantomData,
+        }
+    }
+
+    fn write_line<'a, I>(&mut self, values: I)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let line = Self::format_line(values);
+        writeln!(self.out, "{}", line).unwrap();
+        self.out.flush().unwrap();
+    }
+
+    fn format_line<'a, I>(values: I) -> String
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut line = String::new();
+        for (i, value) in values.into_iter().enumerate() {
+            if i > 0 {
+                line.push(D::SEPARATOR);
+            }
+            D::push_value(&mut line, value);
+        }
+        line
+    }
+
+    fn row_values<'a>(table: &'a Table, row: &'a Row) -> impl Iterator<Item = &'a str> {
+        table
+            .columns
+            .iter()
+            .filter(|column| column.enabled)
+            .map(move |column| row.get(column.header).unwrap_or(""))
+    }
+
+    fn flush_pending_row(&mut self) {
+        if let Some(line) = self.pending_row.take() {
+            writeln!(self.out, "{}", line).unwrap();
+        }
+    }
+}
+
+impl<W: Write, D: Dialect> TablePrinter for DelimitedTablePrinter<W, D> {
+    fn begin_new_table(&mut self, table: &Table) {
+        self.flush_pending_row();
+        let headers: Vec<_> = table
+            .columns
+            .iter()
+            .filter(|column| column.enabled)
+            .map(|column| column.header)
+            .collect();
+        if !self.headers.is_empty() {
+            writeln!(self.out).unwrap();
+            self.out.flush().unwrap();
+        }
+        if headers != self.headers {
+            self.write_line(headers.iter().copied());
+            self.headers = headers;
+        }
+    }
+
+    fn print_row(&mut self, table: &Table, row: &Row) {
+        self.flush_pending_row();
+        self.write_line(Self::row_values(table, row));
+    }
+
+    fn update_row(&mut self, table: &Table, row: &Row) {
+        self.pending_row = Some(Self::format_line(Self::row_values(table, row)));
+    }
+
+    fn finish(&mut self) {
+        self.flush_pending_row();
+        self.out.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_table, column};
+
+    fn print(rows: &[(bool, &[(&str, &str)])]) -> String {
+        let table = build_table();
+        let mut buffer = Vec::new();
+        {
+            let mut table_printer = CsvTablePrinter::new(&mut buffer);
+            table_printer.begin_new_table(&table);
+            for (update, values) in rows {
+                let mut row = table.print_table_line();
+                for (column, value) in values.iter() {
+                    row = row.column(column, *value);
+                }
+                let row = row.finish();
+                if *update {
+                    table_printer.update_row(&table, &row);
+                } else {
+                    table_printer.print_row(&table, &row);
+                }
+            }
+            table_printer.begin_new_table(&table);
+            table_printer.finish();
+        }
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn updates_replace_the_row() {
+        let output = print(&[
+            (false, &[(column::NUMBER, "0"), (column::STATE, "Pressed")]),
+            (true, &[(column::NUMBER, "1"), (column::STATE, "Rpt    1")]),
+            (true, &[(column::NUMBER, "1"), (column::STATE, "Rpt    2")]),
+            (false, &[(column::NUMBER, "1"), (column::STATE, "Released")]),
+        ]);
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("Number,Kind,Synth,State,KeyCode,"));
+        assert!(lines[1].starts_with("0,,,Pressed,"));
+        assert!(lines[2].starts_with("1,,,Rpt    2,"));
+        assert!(lines[3].starts_with("1,,,Released,"));
+        assert_eq!(lines[4], "");
+    }
+
+    #[test]
+    fn values_are_escaped() {
+        let mut line = String::new();
+        Csv::push_value(&mut line, r#"Character(",")"#);
+        assert_eq!(line, r#""Character("","")""#);
+
+        let mut line = String::new();
+        Tsv::push_value(&mut line, "a\tb\\");
+        assert_eq!(line, "a\\tb\\\\");
+    }
+}
@@ -0,0 +1,205 @@
+//! Checking key presses against a reference mapping.
+//!
+//! A reference is a mapping table as printed by `--checklist` with
+//! `--format json`. Every line is a JSON object with the `KeyCode`,
+//! `Modifiers`, `Key` and `Text` of a key, like
+//!
+//! ```json
+//! {"KeyCode":"KeyQ","Modifiers":"Shift","Key":{"Character":"Q"},"Text":"Q"}
+//! ```
+//!
+//! Other fields are ignored, and so are lines without a `Key`, which is what
+//! the checklist prints for skipped keys. `Modifiers` is one of `None`,
+//! `Shift`, `AltGr` or `Shift+AltGr`.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+use serde_json::{json, Map};
+use winit::{
+    event::ElementState,
+    keyboard::{Key, KeyCode},
+};
+
+use crate::{
+    checklist::{Combination, HeldModifiers},
+    key_code_to_string, key_to_string, nice_text,
+    record::RecordedEvent,
+    Cell,
+};
+
+#[derive(Deserialize)]
+struct ReferenceLine {
+    #[serde(rename = "KeyCode")]
+    key_code: KeyCode,
+    #[serde(rename = "Modifiers")]
+    modifiers: String,
+    #[serde(rename = "Key", default)]
+    key: Option<Key>,
+    #[serde(rename = "Text", default)]
+    text: Option<String>,
+}
+
+struct Expected {
+    key: Key,
+    text: Option<String>,
+}
+
+pub struct Expectations {
+    expected: HashMap<(KeyCode, Combination), Expected>,
+    held: HeldModifiers,
+    /// The number of presses in the current table that had a reference.
+    checked: usize,
+    /// The keys in the current table that didn't match their reference.
+    mismatched: Vec<KeyCode>,
+}
+
+impl Expectations {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        Self::parse(&text)
+            .map_err(|err| format!("Invalid reference in {}: {}", path.display(), err))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut expected = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line: ReferenceLine = serde_json::from_str(line)
+                .map_err(|err| format!("line {}: {}", number + 1, err))?;
+            let combination = Combination::from_name(&line.modifiers).ok_or_else(|| {
+                format!(
+                    "line {}: unknown modifiers {:?}, expected \"None\", \"Shift\", \"AltGr\" or \"Shift+AltGr\"",
+                    number + 1,
+                    line.modifiers
+                )
+            })?;
+            if let Some(key) = line.key {
+                let text = line.text;
+                expected.insert((line.key_code, combination), Expected { key, text });
+            }
+        }
+
+        Ok(Self {
+            expected,
+            held: HeldModifiers::default(),
+            checked: 0,
+            mismatched: Vec::new(),
+        })
+    }
+
+    /// Checks key presses against the reference. Returns the expected values
+    /// that a press didn't match.
+    pub fn handle_event(&mut self, event: &RecordedEvent) -> Option<Cell> {
+        self.held.handle_event(event);
+        let event = match event {
+            RecordedEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed && !event.repeat =>
+            {
+                event
+            }
+            _ => return None,
+        };
+        let combination = self.held.combination()?;
+        let expected = self.expected.get(&(event.physical_key, combination))?;
+        self.checked += 1;
+
+        let mut text = Vec::new();
+        let mut value = Map::new();
+        if event.logical_key != expected.key {
+            text.push(format!("Key={}", key_to_string(&expected.key)));
+            value.insert("Key".to_string(), json!(expected.key));
+        }
+        if event.text != expected.text {
+            let expected_text = expected.text.as_ref().map(nice_text);
+            text.push(format!(
+                "Text={}",
+                expected_text.as_deref().unwrap_or("None")
+            ));
+            value.insert("Text".to_string(), json!(expected.text));
+        }
+        if text.is_empty() {
+            return None;
+        }
+
+        self.mismatched.push(event.physical_key);
+        Some(Cell::typed(text.join(" "), &value))
+    }
+
+    /// Sums up the presses that were checked since the last summary, along
+    /// with the number of them that didn't match. Returns `None` if there
+    /// weren't any.
+    pub fn summary(&mut self) -> Option<(Cell, usize)> {
+        if self.checked == 0 {
+            return None;
+        }
+        let checked = std::mem::take(&mut self.checked);
+        let mismatched = std::mem::take(&mut self.mismatched);
+        let text = if mismatched.is_empty() {
+            format!("{}/{} presses matched", checked, checked)
+        } else {
+            let keys: Vec<_> = mismatched.iter().map(key_code_to_string).collect();
+            format!(
+                "{}/{} presses didn't match: {}",
+                mismatched.len(),
+                checked,
+                keys.join(", ")
+            )
+        };
+        let value = json!({ "checked": checked, "mismatched": mismatched });
+        Some((Cell::typed(text, &value), mismatched.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::KeyEventRecord;
+
+    const REFERENCE: &str = r#"
+{"KeyCode":"KeyQ","Modifiers":"None","Key":{"Character":"q"},"Text":"q"}
+{"KeyCode":"KeyQ","Modifiers":"Shift","Key":{"Character":"Q"},"Text":"Q"}
+{"KeyCode":"IntlRo","Modifiers":"None","Key":null,"Text":null}
+"#;
+
+    #[test]
+    fn presses_are_checked_against_the_reference() {
+        let mut expectations = Expectations::parse(REFERENCE).unwrap();
+        let mut check = |physical_key, text| {
+            expectations
+                .handle_event(
+                    &KeyEventRecord::for_test(physical_key, ElementState::Pressed)
+                        .with_text(text)
+                        .into_event(),
+                )
+                .map(|cell| cell.text)
+        };
+
+        assert_eq!(check(KeyCode::KeyQ, "q"), None);
+        assert_eq!(check(KeyCode::IntlRo, "\\"), None);
+        assert_eq!(check(KeyCode::ShiftLeft, ""), None);
+        assert_eq!(
+            check(KeyCode::KeyQ, "q").as_deref(),
+            Some(r#"Key=Character("Q") Text=Q"#)
+        );
+
+        let (summary, mismatch_count) = expectations.summary().unwrap();
+        assert_eq!(summary.text, "1/2 presses didn't match: KeyQ");
+        assert_eq!(mismatch_count, 1);
+        assert!(expectations.summary().is_none());
+    }
+
+    #[test]
+    fn unknown_modifiers_are_an_error() {
+        let reference = r#"{"KeyCode":"KeyQ","Modifiers":"Control","Key":{"Character":"q"}}"#;
+        let err = Expectations::parse(reference).err().unwrap();
+        assert!(
+            err.starts_with("line 1: unknown modifiers \"Control\""),
+            "{}",
+            err
+        );
+    }
+}
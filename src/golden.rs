@@ -0,0 +1,308 @@
+//! Golden-file tests for the markdown output.
+//!
+//! Each test feeds a synthetic event stream through a [`Session`] and compares
+//! the markdown against `tests/golden/<name>.md`. Run the tests with
+//! `UPDATE_GOLDEN=1` to rewrite the golden files from the current output.
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use winit::{
+    event::{ElementState, Ime},
+    keyboard::{Key, KeyCode, KeyLocation, ModifiersState},
+};
+
+use crate::{
+    build_table, column,
+    record::{KeyEventRecord, RecordedEvent},
+    session::Session,
+    MarkdownTablePrinter, Table, TableColumn, TablePrinter,
+};
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.md", name))
+}
+
+fn assert_golden(name: &str, actual: &str) {
+    let path = golden_path(name);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!(
+            "Failed to read {}: {}. Run with UPDATE_GOLDEN=1 to create it.",
+            path.display(),
+            err
+        )
+    });
+
+    if actual != expected {
+        let (line, (expected_line, actual_line)) = expected
+            .lines()
+            .chain(std::iter::repeat("<EOF>"))
+            .zip(actual.lines().chain(std::iter::repeat("<EOF>")))
+            .enumerate()
+            .find(|(_, (expected, actual))| expected != actual)
+            .unwrap();
+        panic!(
+            "Output differs from {} at line {}:\nexpected: {}\nactual:   {}\n\
+             Run with UPDATE_GOLDEN=1 to update it.",
+            path.display(),
+            line + 1,
+            expected_line,
+            actual_line
+        );
+    }
+}
+
+/// Renders `events`, given as milliseconds since the start and the event.
+fn render(table: Table, events: &[(u64, RecordedEvent)]) -> String {
+    let mut buffer = Vec::new();
+    {
+        let mut table_printer = MarkdownTablePrinter::new(&mut buffer);
+        let start = Instant::now();
+        let mut session = Session::new(table, start);
+
+        table_printer.begin_new_table(session.table());
+        for (millis, event) in events {
+            let now = start + Duration::from_millis(*millis);
+            let mut output = session.tick(now);
+            output.extend(session.handle_event(now, event));
+            for output in output {
+                output.print(session.table(), &mut table_printer);
+            }
+        }
+        table_printer.finish();
+    }
+    String::from_utf8(buffer).unwrap()
+}
+
+fn key_event(
+    physical_key: KeyCode,
+    logical_key: Key,
+    text: Option<&str>,
+    state: ElementState,
+    repeat: bool,
+) -> RecordedEvent {
+    let location = match physical_key {
+        KeyCode::ShiftLeft | KeyCode::ControlLeft => KeyLocation::Left,
+        KeyCode::ShiftRight | KeyCode::ControlRight => KeyLocation::Right,
+        _ => KeyLocation::Standard,
+    };
+    KeyEventRecord {
+        location,
+        scan_code: scan_code(physical_key),
+        key_without_modifiers: Some(logical_key.clone()),
+        text_with_all_modifiers: text.map(str::to_string),
+        ..KeyEventRecord::for_test(physical_key, state)
+            .with_logical_key(logical_key, text)
+            .with_repeat(repeat)
+    }
+    .into_event()
+}
+
+/// A press and release of a key that produces `text`.
+fn type_text(physical_key: KeyCode, text: &str) -> [RecordedEvent; 2] {
+    let key = Key::Character(text.into());
+    [
+        key_event(
+            physical_key,
+            key.clone(),
+            Some(text),
+            ElementState::Pressed,
+            false,
+        ),
+        key_event(physical_key, key, Some(text), ElementState::Released, false),
+    ]
+}
+
+fn device_key(physical_key: KeyCode, state: ElementState) -> RecordedEvent {
+    RecordedEvent::DeviceKey {
+        physical_key,
+        state,
+        scan_code: scan_code(physical_key),
+    }
+}
+
+/// Set 1 scan codes for a few keys, to fill the Scancode column.
+fn scan_code(physical_key: KeyCode) -> Option<u32> {
+    match physical_key {
+        KeyCode::ShiftLeft => Some(0x2a),
+        KeyCode::KeyA => Some(0x1e),
+        KeyCode::Enter => Some(0x1c),
+        _ => None,
+    }
+}
+
+#[test]
+fn shifted_key_with_repeats() {
+    use ElementState::{Pressed, Released};
+
+    let shift = ModifiersState::SHIFT;
+    let a = Key::Character("A".into());
+    let events = [
+        (0, device_key(KeyCode::ShiftLeft, Pressed)),
+        (0, RecordedEvent::ModifiersChanged { modifiers: shift }),
+        (
+            0,
+            key_event(KeyCode::ShiftLeft, Key::Shift, None, Pressed, false),
+        ),
+        (100, device_key(KeyCode::KeyA, Pressed)),
+        (
+            100,
+            key_event(KeyCode::KeyA, a.clone(), Some("A"), Pressed, false),
+        ),
+        (
+            600,
+            key_event(KeyCode::KeyA, a.clone(), Some("A"), Pressed, true),
+        ),
+        (
+            633,
+            key_event(KeyCode::KeyA, a.clone(), Some("A"), Pressed, true),
+        ),
+        (
+            666,
+            key_event(KeyCode::KeyA, a.clone(), Some("A"), Pressed, true),
+        ),
+        (700, device_key(KeyCode::KeyA, Released)),
+        (700, key_event(KeyCode::KeyA, a, Some("A"), Released, false)),
+        (800, device_key(KeyCode::ShiftLeft, Released)),
+        (
+            800,
+            RecordedEvent::ModifiersChanged {
+                modifiers: ModifiersState::empty(),
+            },
+        ),
+        (
+            800,
+            key_event(KeyCode::ShiftLeft, Key::Shift, None, Released, false),
+        ),
+        (900, RecordedEvent::Focused { focused: false }),
+        // Arrives after the table timed out, so it begins a new table.
+        (10_000, RecordedEvent::Focused { focused: true }),
+        (10_100, RecordedEvent::DeadKeyReset),
+    ];
+
+    assert_golden("shifted_key_with_repeats", &render(build_table(), &events));
+}
+
+#[test]
+fn unicode_text() {
+    let mut events = Vec::new();
+    let mut type_at = |millis: u64, physical_key, text| {
+        for event in type_text(physical_key, text).iter() {
+            events.push((millis, event.clone()));
+        }
+    };
+
+    // Wide characters take up two columns each.
+    type_at(0, KeyCode::KeyA, "あ");
+    type_at(100, KeyCode::KeyK, "漢字");
+    // Combining characters don't take up a column of their own.
+    type_at(200, KeyCode::KeyE, "e\u{301}");
+    type_at(300, KeyCode::Quote, "\u{301}");
+    // Control characters and whitespace are escaped by `nice_text`.
+    type_at(400, KeyCode::Enter, "\r");
+    type_at(500, KeyCode::Tab, "\t");
+    type_at(600, KeyCode::Escape, "\u{1b}");
+    type_at(700, KeyCode::Space, " ");
+    type_at(800, KeyCode::Backspace, "\u{8}");
+
+    events.push((
+        900,
+        RecordedEvent::Ime {
+            ime: Ime::Preedit("にほんご".to_string(), Some((0, 12))),
+        },
+    ));
+    events.push((
+        1000,
+        RecordedEvent::Ime {
+            ime: Ime::Commit("日本語".to_string()),
+        },
+    ));
+
+    assert_golden("unicode_text", &render(build_table(), &events));
+}
+
+#[test]
+fn disabled_columns_and_header_widths() {
+    let column = |header, normal_width, extended_width, use_extended_width, enabled| TableColumn {
+        header,
+        normal_width,
+        extended_width,
+        use_extended_width,
+        enabled,
+    };
+
+    let mut table = Table::new();
+    // Narrower than the header, so the header's length is used.
+    table.add_column(column(column::NUMBER, 0, 0, false, true));
+    table.add_column(column(column::KIND, 2, 0, false, true));
+    table.add_column(column(column::SYNTH, 5, 0, false, false));
+    table.add_column(column(column::STATE, 8, 0, false, true));
+    table.add_column(column(column::KEY_CODE, 10, 30, true, true));
+    table.add_column(column(column::KEY, 25, 42, false, false));
+    table.add_column(column(column::TEXT, 4, 0, false, true));
+
+    let mut events = Vec::new();
+    for (millis, physical_key, text) in [
+        (0, KeyCode::KeyQ, "q"),
+        (100, KeyCode::Digit1, "1"),
+        (200, KeyCode::Minus, "-"),
+    ]
+    .iter()
+    {
+        for event in type_text(*physical_key, text).iter() {
+            events.push((*millis, event.clone()));
+        }
+    }
+
+    assert_golden(
+        "disabled_columns_and_header_widths",
+        &render(table, &events),
+    );
+}
+
+#[test]
+fn ime_composition() {
+    let mut table = build_table();
+    table.select_columns(&[
+        column::NUMBER,
+        column::KIND,
+        column::STATE,
+        column::PREEDIT,
+        column::COMMIT,
+        column::COMPOSED,
+    ]);
+
+    let ime = |ime| RecordedEvent::Ime { ime };
+    let preedit = |text: &str, cursor| ime(Ime::Preedit(text.to_string(), cursor));
+    let events = [
+        (0, ime(Ime::Enabled)),
+        (100, preedit("に", Some((3, 3)))),
+        (200, preedit("にほんご", Some((12, 12)))),
+        // Converting selects the whole preedit, then the first clause.
+        (300, preedit("日本語", Some((0, 9)))),
+        (400, preedit("日本語", Some((0, 6)))),
+        // Hidden cursor.
+        (500, preedit("日本語", None)),
+        (600, preedit("", None)),
+        (600, ime(Ime::Commit("日本語".to_string()))),
+        (700, preedit("を", Some((3, 3)))),
+        (800, preedit("", None)),
+        (800, ime(Ime::Commit("を".to_string()))),
+        (900, ime(Ime::Disabled)),
+    ];
+
+    assert_golden("ime_composition", &render(table, &events));
+}
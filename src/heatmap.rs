@@ -0,0 +1,174 @@
+//! A keyboard diagram that shows which keys are held and how often each key
+//! has been pressed.
+//!
+//! Held keys are colored by where their press was seen, so a key that only
+//! the window or only the raw device input knows about stands out.
+
+use std::collections::{HashMap, HashSet};
+
+use winit::{event::ElementState, keyboard::KeyCode};
+
+use crate::{
+    canvas::{Canvas, BLACK, WHITE},
+    layout::{self, KeyPosition, PhysicalLayout},
+    record::RecordedEvent,
+};
+
+const BORDER: u32 = 0x0080_8080;
+/// Held according to both the window and the device events.
+const HELD_BOTH: u32 = 0x0060_c060;
+/// Held according to the window events, but not the device events.
+const HELD_WINDOW_ONLY: u32 = 0x0050_90e0;
+/// Held according to the device events, but not the window events.
+const HELD_DEVICE_ONLY: u32 = 0x00f0_a030;
+/// The color of the keys that have been pressed the most.
+const HOTTEST: u32 = 0x00ff_6060;
+
+pub struct KeyboardHeatmap {
+    keys: Vec<KeyPosition>,
+    window_pressed: HashSet<KeyCode>,
+    device_pressed: HashSet<KeyCode>,
+    press_counts: HashMap<KeyCode, u32>,
+}
+
+impl KeyboardHeatmap {
+    pub fn new(layout: PhysicalLayout) -> Self {
+        Self {
+            keys: layout.keys(),
+            window_pressed: HashSet::new(),
+            device_pressed: HashSet::new(),
+            press_counts: HashMap::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &RecordedEvent) {
+        match event {
+            RecordedEvent::KeyboardInput { event, .. } => {
+                self.update(event.physical_key, event.state, false);
+            }
+            RecordedEvent::DeviceKey {
+                physical_key,
+                state,
+                ..
+            } => self.update(*physical_key, *state, true),
+            // The releases of a table control chord's keys are taken out.
+            RecordedEvent::TableControl => {
+                self.window_pressed.clear();
+                self.device_pressed.clear();
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, key_code: KeyCode, state: ElementState, device: bool) {
+        let was_held = self.is_held(key_code);
+        let pressed = if device {
+            &mut self.device_pressed
+        } else {
+            &mut self.window_pressed
+        };
+        match state {
+            ElementState::Pressed => {
+                pressed.insert(key_code);
+                if !was_held {
+                    *self.press_counts.entry(key_code).or_insert(0) += 1;
+                }
+            }
+            ElementState::Released => {
+                pressed.remove(&key_code);
+            }
+        }
+    }
+
+    fn is_held(&self, key_code: KeyCode) -> bool {
+        self.window_pressed.contains(&key_code) || self.device_pressed.contains(&key_code)
+    }
+
+    fn key_color(&self, key_code: KeyCode, max_count: u32) -> u32 {
+        match (
+            self.window_pressed.contains(&key_code),
+            self.device_pressed.contains(&key_code),
+        ) {
+            (true, true) => HELD_BOTH,
+            (true, false) => HELD_WINDOW_ONLY,
+            (false, true) => HELD_DEVICE_ONLY,
+            (false, false) => {
+                let count = self.press_counts.get(&key_code).copied().unwrap_or(0);
+                if count == 0 {
+                    WHITE
+                } else {
+                    // Even a single press gets a bit of color.
+                    blend(WHITE, HOTTEST, 0.2 + 0.8 * count as f32 / max_count as f32)
+                }
+            }
+        }
+    }
+
+    /// Draws the diagram along the bottom of the canvas, using at most half
+    /// of its height. Returns the top of the diagram.
+    pub fn draw(&self, canvas: &mut Canvas<'_>) -> usize {
+        let (layout_width, layout_height) = layout::size(&self.keys);
+        let pixels_per_unit = (canvas.width() as f32 / layout_width as f32)
+            .min(canvas.height() as f32 / 2.0 / layout_height as f32);
+        let to_pixels = |units: u16| (units as f32 * pixels_per_unit) as usize;
+        let y = canvas.height() - to_pixels(layout_height);
+        canvas.fill_rect(0, y, canvas.width(), canvas.height() - y, WHITE);
+        let max_count = self.press_counts.values().copied().max().unwrap_or(0);
+
+        for key in &self.keys {
+            let x = to_pixels(key.x);
+            let key_y = y + to_pixels(key.y);
+            let width = to_pixels(key.x + key.width) - x;
+            let height = to_pixels(key.y + key.height) - to_pixels(key.y);
+            if width < 3 || height < 3 {
+                continue;
+            }
+
+            canvas.fill_rect(x, key_y, width, height, BORDER);
+            canvas.fill_rect(
+                x + 1,
+                key_y + 1,
+                width - 2,
+                height - 2,
+                self.key_color(key.key_code, max_count),
+            );
+
+            let label_width = key.label.len() * canvas.cell_width();
+            if label_width < width {
+                canvas.draw_text(x + (width - label_width) / 2, key_y + 2, key.label, BLACK);
+            }
+        }
+
+        y
+    }
+}
+
+fn blend(from: u32, to: u32, amount: f32) -> u32 {
+    let channel = |shift: u32| {
+        let from = (from >> shift & 0xff) as f32;
+        let to = (to >> shift & 0xff) as f32;
+        ((from + (to - from) * amount.min(1.0)) as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::device_key;
+
+    #[test]
+    fn device_only_presses_are_distinguished() {
+        let mut heatmap = KeyboardHeatmap::new(PhysicalLayout::Ansi);
+
+        heatmap.handle_event(&device_key(KeyCode::KeyA, ElementState::Pressed));
+        assert_eq!(heatmap.key_color(KeyCode::KeyA, 1), HELD_DEVICE_ONLY);
+
+        heatmap.handle_event(&device_key(KeyCode::KeyA, ElementState::Released));
+        heatmap.handle_event(&device_key(KeyCode::KeyA, ElementState::Pressed));
+        heatmap.handle_event(&device_key(KeyCode::KeyA, ElementState::Released));
+        assert_eq!(heatmap.press_counts[&KeyCode::KeyA], 2);
+        assert_eq!(heatmap.key_color(KeyCode::KeyA, 2), HOTTEST);
+        assert_eq!(heatmap.key_color(KeyCode::KeyB, 2), WHITE);
+    }
+}
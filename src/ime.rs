@@ -0,0 +1,63 @@
+//! Rendering of IME events.
+//!
+//! The cursor of a preedit is given as a range of byte offsets into the
+//! preedit string. It's shown by putting brackets around the selected text, or
+//! `[]` where the cursor is if nothing is selected, like `にほ[ん]ご`.
+
+use serde_json::json;
+
+use crate::{nice_text, Cell};
+
+/// The preedit string with its cursor marked.
+///
+/// A preedit without a cursor is shown as is. A cursor that doesn't fall on
+/// character boundaries is reported along with the plain string. The JSON
+/// value has the cursor both in bytes and in characters.
+pub fn preedit_cell(text: &str, cursor: Option<(usize, usize)>) -> Cell {
+    let (start, end) = match cursor {
+        Some(cursor) => cursor,
+        None => return Cell::typed(nice_text(text), &json!({ "text": text, "cursor": null })),
+    };
+
+    if !text.is_char_boundary(start) || !text.is_char_boundary(end) || start > end {
+        let value = json!({ "text": text, "cursor": [start, end] });
+        return Cell::typed(
+            format!("{} (invalid cursor {}..{})", nice_text(text), start, end),
+            &value,
+        );
+    }
+
+    let marked = format!("{}[{}]{}", &text[..start], &text[start..end], &text[end..]);
+    let start_char = text[..start].chars().count();
+    let end_char = start_char + text[start..end].chars().count();
+    let value = json!({
+        "text": text,
+        "cursor": [start, end],
+        "cursor_chars": [start_char, end_char],
+    });
+    Cell::typed(nice_text(marked), &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preedit_cursors_are_marked() {
+        let preedit = |cursor| preedit_cell("にほんご", cursor);
+
+        assert_eq!(preedit(None).text, "にほんご");
+        assert_eq!(preedit(Some((0, 12))).text, "[にほんご]");
+        assert_eq!(preedit(Some((6, 9))).text, "にほ[ん]ご");
+        assert_eq!(preedit(Some((12, 12))).text, "にほんご[]");
+        assert_eq!(
+            preedit(Some((6, 9))).value,
+            json!({ "text": "にほんご", "cursor": [6, 9], "cursor_chars": [2, 3] })
+        );
+        assert_eq!(preedit(Some((1, 3))).text, "にほんご (invalid cursor 1..3)");
+        assert_eq!(
+            preedit(Some((0, 13))).text,
+            "にほんご (invalid cursor 0..13)"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! JSON Lines output, for comparing sessions with other tools.
+//!
+//! Every row is printed as an object on its own line. The keys are the headers
+//! of the enabled columns, plus `table`, which counts the tables from zero.
+//! Values keep their types: `Synth` is a boolean, `Number` and `Scancode` are
+//! numbers, and keys and key codes are serialized the same way as in
+//! recordings.
+
+use std::io::Write;
+
+use serde_json::{Map, Value};
+
+use crate::{Row, Table, TablePrinter};
+
+pub struct JsonTablePrinter<W> {
+    out: W,
+    table_number: Option<u64>,
+    pending_row: Option<Value>,
+}
+
+impl<W: Write> JsonTablePrinter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            table_number: None,
+            pending_row: None,
+        }
+    }
+
+    fn row_object(&self, table: &Table, row: &Row) -> Value {
+        let mut object = Map::new();
+        object.insert("table".to_string(), self.table_number.unwrap_or(0).into());
+        for column in table.columns.iter().filter(|column| column.enabled) {
+            let value = row.value(column.header).cloned().unwrap_or(Value::Null);
+            object.insert(column.header.to_string(), value);
+        }
+        Value::Object(object)
+    }
+
+    fn write_row(&mut self, row: &Value) {
+        serde_json::to_writer(&mut self.out, row).unwrap();
+        writeln!(self.out).unwrap();
+        self.out.flush().unwrap();
+    }
+
+    fn flush_pending_row(&mut self) {
+        if let Some(row) = self.pending_row.take() {
+            self.write_row(&row);
+        }
+    }
+}
+
+impl<W: Write> TablePrinter for JsonTablePrinter<W> {
+    fn begin_new_table(&mut self, _: &Table) {
+        self.flush_pending_row();
+        self.table_number = Some(self.table_number.map_or(0, |number| number + 1));
+    }
+
+    fn print_row(&mut self, table: &Table, row: &Row) {
+        self.flush_pending_row();
+        let row = self.row_object(table, row);
+        self.write_row(&row);
+    }
+
+    fn update_row(&mut self, table: &Table, row: &Row) {
+        self.pending_row = Some(self.row_object(table, row));
+    }
+
+    fn finish(&mut self) {
+        self.flush_pending_row();
+        self.out.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use winit::keyboard::Key;
+
+    use super::*;
+    use crate::{build_table, column, Cell};
+
+    #[test]
+    fn rows_have_typed_values() {
+        let table = build_table();
+        let key: Key = Key::Character("a".into());
+        let row = |state: Cell| {
+            table
+                .print_table_line()
+                .column(column::NUMBER, 3u16)
+                .column(column::SYNTH, false)
+                .column(column::STATE, state)
+                .column(column::KEY, Cell::typed("a".to_string(), &key))
+                .finish()
+        };
+
+        let mut buffer = Vec::new();
+        {
+            let mut table_printer = JsonTablePrinter::new(&mut buffer);
+            table_printer.begin_new_table(&table);
+            table_printer.update_row(&table, &row(Cell::typed(String::new(), &json!(1))));
+            table_printer.update_row(&table, &row(Cell::typed(String::new(), &json!(2))));
+            table_printer.begin_new_table(&table);
+            table_printer.print_row(&table, &row("Released".into()));
+            table_printer.finish();
+        }
+
+        let rows: Vec<Value> = String::from_utf8(buffer)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["table"], json!(0));
+        assert_eq!(rows[0][column::NUMBER], json!(3));
+        assert_eq!(rows[0][column::SYNTH], json!(false));
+        assert_eq!(rows[0][column::STATE], json!(2));
+        assert_eq!(rows[0][column::KEY], json!({ "Character": "a" }));
+        assert_eq!(rows[0][column::TEXT], Value::Null);
+        assert_eq!(rows[1]["table"], json!(1));
+        assert_eq!(rows[1][column::STATE], json!("Released"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Physical keyboard layouts, for drawing a keyboard into the window.
+//!
+//! Positions and sizes are given in quarters of the width of a regular key.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use winit::keyboard::KeyCode;
+
+/// One unit, which is the width of a regular key.
+const U: u16 = 4;
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PhysicalLayout {
+    /// The US layout, with a wide left Shift and a single row Enter
+    #[default]
+    Ansi,
+    /// The European layout, with an extra key next to the left Shift and a
+    /// two row Enter
+    Iso,
+    /// The Japanese layout, with Yen and Ro keys and the IME keys around the
+    /// space bar
+    Jis,
+    /// The Brazilian layout, which is ISO with an extra key next to the right
+    /// Shift
+    Abnt2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPosition {
+    pub key_code: KeyCode,
+    pub label: &'static str,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Lays out a row of keys from left to right.
+struct RowBuilder<'a> {
+    keys: &'a mut Vec<KeyPosition>,
+    x: u16,
+    y: u16,
+}
+
+impl<'a> RowBuilder<'a> {
+    fn new(keys: &'a mut Vec<KeyPosition>, y: u16) -> Self {
+        Self { keys, x: 0, y }
+    }
+
+    fn key(self, key_code: KeyCode, label: &'static str) -> Self {
+        self.wide_key(key_code, label, U)
+    }
+
+    fn wide_key(self, key_code: KeyCode, label: &'static str, width: u16) -> Self {
+        self.tall_key(key_code, label, width, U)
+    }
+
+    fn tall_key(mut self, key_code: KeyCode, label: &'static str, width: u16, height: u16) -> Self {
+        self.keys.push(KeyPosition {
+            key_code,
+            label,
+            x: self.x,
+            y: self.y,
+            width,
+            height,
+        });
+        self.x += width;
+        self
+    }
+
+    fn keys(mut self, keys: &[(KeyCode, &'static str)]) -> Self {
+        for (key_code, label) in keys {
+            self = self.key(*key_code, label);
+        }
+        self
+    }
+
+    fn gap(mut self, width: u16) -> Self {
+        self.x += width;
+        self
+    }
+
+    /// Continues at `x`, for the blocks to the right of the main block.
+    fn at(mut self, x: u16) -> Self {
+        self.x = x;
+        self
+    }
+}
+
+/// Where the navigation block and the numpad begin.
+const NAVIGATION_X: u16 = 15 * U + 1;
+const NUMPAD_X: u16 = 18 * U + 2;
+
+impl PhysicalLayout {
+    pub fn keys(self) -> Vec<KeyPosition> {
+        use KeyCode::*;
+
+        let mut keys = Vec::new();
+
+        RowBuilder::new(&mut keys, 0)
+            .key(Escape, "Esc")
+            .gap(U)
+            .keys(&[(F1, "F1"), (F2, "F2"), (F3, "F3"), (F4, "F4")])
+            .gap(U / 2)
+            .keys(&[(F5, "F5"), (F6, "F6"), (F7, "F7"), (F8, "F8")])
+            .gap(U / 2)
+            .keys(&[(F9, "F9"), (F10, "F10"), (F11, "F11"), (F12, "F12")])
+            .at(NAVIGATION_X)
+            .keys(&[(PrintScreen, "PrSc"), (ScrollLock, "ScLk"), (Pause, "Paus")]);
+
+        let y = U + U / 2;
+        let row = RowBuilder::new(&mut keys, y).key(Backquote, "`").keys(&[
+            (Digit1, "1"),
+            (Digit2, "2"),
+            (Digit3, "3"),
+            (Digit4, "4"),
+            (Digit5, "5"),
+            (Digit6, "6"),
+            (Digit7, "7"),
+            (Digit8, "8"),
+            (Digit9, "9"),
+            (Digit0, "0"),
+            (Minus, "-"),
+            (Equal, "="),
+        ]);
+        let row = match self {
+            PhysicalLayout::Jis => row.key(IntlYen, "Yen").key(Backspace, "Bksp"),
+            _ => row.wide_key(Backspace, "Bksp", 2 * U),
+        };
+        row.at(NAVIGATION_X)
+            .keys(&[(Insert, "Ins"), (Home, "Home"), (PageUp, "PgUp")])
+            .at(NUMPAD_X)
+            .keys(&[
+                (NumLock, "Num"),
+                (NumpadDivide, "/"),
+                (NumpadMultiply, "*"),
+                (NumpadSubtract, "-"),
+            ]);
+
+        let y = y + U;
+        let row = RowBuilder::new(&mut keys, y)
+            .wide_key(Tab, "Tab", U + U / 2)
+            .keys(&[
+                (KeyQ, "Q"),
+                (KeyW, "W"),
+                (KeyE, "E"),
+                (KeyR, "R"),
+                (KeyT, "T"),
+                (KeyY, "Y"),
+                (KeyU, "U"),
+                (KeyI, "I"),
+                (KeyO, "O"),
+                (KeyP, "P"),
+                (BracketLeft, "["),
+                (BracketRight, "]"),
+            ]);
+        let row = match self {
+            PhysicalLayout::Ansi => row.wide_key(Backslash, "\\", U + U / 2),
+            PhysicalLayout::Iso | PhysicalLayout::Jis | PhysicalLayout::Abnt2 => {
+                row.gap(U / 4).tall_key(Enter, "Ent", U + U / 4, 2 * U)
+            }
+        };
+        row.at(NAVIGATION_X)
+            .keys(&[(Delete, "Del"), (End, "End"), (PageDown, "PgDn")])
+            .at(NUMPAD_X)
+            .keys(&[(Numpad7, "7"), (Numpad8, "8"), (Numpad9, "9")])
+            .tall_key(NumpadAdd, "+", U, 2 * U);
+
+        let y = y + U;
+        let row = RowBuilder::new(&mut keys, y)
+            .wide_key(CapsLock, "Caps", U + 3 * U / 4)
+            .keys(&[
+                (KeyA, "A"),
+                (KeyS, "S"),
+                (KeyD, "D"),
+                (KeyF, "F"),
+                (KeyG, "G"),
+                (KeyH, "H"),
+                (KeyJ, "J"),
+                (KeyK, "K"),
+                (KeyL, "L"),
+                (Semicolon, ";"),
+                (Quote, "'"),
+            ]);
+        match self {
+            PhysicalLayout::Ansi => row.wide_key(Enter, "Enter", 2 * U + U / 4),
+            PhysicalLayout::Iso | PhysicalLayout::Jis | PhysicalLayout::Abnt2 => {
+                row.key(Backslash, "\\")
+            }
+        }
+        .at(NUMPAD_X)
+        .keys(&[(Numpad4, "4"), (Numpad5, "5"), (Numpad6, "6")]);
+
+        let y = y + U;
+        let row = RowBuilder::new(&mut keys, y);
+        let row = match self {
+            PhysicalLayout::Ansi | PhysicalLayout::Jis => {
+                row.wide_key(ShiftLeft, "Shift", 2 * U + U / 4)
+            }
+            PhysicalLayout::Iso | PhysicalLayout::Abnt2 => row
+                .wide_key(ShiftLeft, "Shft", U + U / 4)
+                .key(IntlBackslash, "\\"),
+        };
+        let row = row.keys(&[
+            (KeyZ, "Z"),
+            (KeyX, "X"),
+            (KeyC, "C"),
+            (KeyV, "V"),
+            (KeyB, "B"),
+            (KeyN, "N"),
+            (KeyM, "M"),
+            (Comma, ","),
+            (Period, "."),
+            (Slash, "/"),
+        ]);
+        let row = match self {
+            PhysicalLayout::Ansi | PhysicalLayout::Iso => {
+                row.wide_key(ShiftRight, "Shift", 2 * U + 3 * U / 4)
+            }
+            PhysicalLayout::Jis | PhysicalLayout::Abnt2 => {
+                row.key(IntlRo, "Ro")
+                    .wide_key(ShiftRight, "Shft", U + 3 * U / 4)
+            }
+        };
+        row.at(NAVIGATION_X + U)
+            .key(ArrowUp, "Up")
+            .at(NUMPAD_X)
+            .keys(&[(Numpad1, "1"), (Numpad2, "2"), (Numpad3, "3")])
+            .tall_key(NumpadEnter, "Ent", U, 2 * U);
+
+        let y = y + U;
+        let modifier = U + U / 4;
+        let row = RowBuilder::new(&mut keys, y)
+            .wide_key(ControlLeft, "Ctrl", modifier)
+            .wide_key(SuperLeft, "Supr", modifier)
+            .wide_key(AltLeft, "Alt", modifier);
+        let row = match self {
+            PhysicalLayout::Jis => row
+                .wide_key(NonConvert, "NCnv", modifier)
+                .wide_key(Space, "Space", 3 * U + 3 * U / 4)
+                .wide_key(Convert, "Conv", modifier)
+                .wide_key(KanaMode, "Kana", modifier)
+                .wide_key(AltRight, "Alt", modifier),
+            _ => row
+                .wide_key(Space, "Space", 6 * U + U / 4)
+                .wide_key(AltRight, "Alt", modifier)
+                .wide_key(SuperRight, "Supr", modifier),
+        };
+        row.wide_key(ContextMenu, "Menu", modifier)
+            .wide_key(ControlRight, "Ctrl", modifier)
+            .at(NAVIGATION_X)
+            .keys(&[(ArrowLeft, "<"), (ArrowDown, "Dn"), (ArrowRight, ">")])
+            .at(NUMPAD_X)
+            .wide_key(Numpad0, "0", 2 * U)
+            .key(NumpadDecimal, ".");
+
+        keys
+    }
+}
+
+/// The size of a layout, in the same units as its keys.
+pub fn size(keys: &[KeyPosition]) -> (u16, u16) {
+    keys.iter().fold((0, 0), |(width, height), key| {
+        (width.max(key.x + key.width), height.max(key.y + key.height))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &KeyPosition, b: &KeyPosition) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn keys_are_unique_and_dont_overlap() {
+        for layout in PhysicalLayout::value_variants() {
+            let keys = layout.keys();
+            for (i, a) in keys.iter().enumerate() {
+                for b in &keys[i + 1..] {
+                    assert_ne!(a.key_code, b.key_code, "{:?}", layout);
+                    assert!(!overlaps(a, b), "{:?}: {:?} and {:?}", layout, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn main_block_rows_have_the_same_width() {
+        for layout in PhysicalLayout::value_variants() {
+            let keys = layout.keys();
+            for y in [U + U / 2, 2 * U + U / 2, 3 * U + U / 2, 4 * U + U / 2].iter() {
+                let row_end = keys
+                    .iter()
+                    .filter(|key| key.y <= *y && *y < key.y + key.height)
+                    .filter(|key| key.x < NAVIGATION_X)
+                    .map(|key| key.x + key.width)
+                    .max();
+                assert_eq!(row_end, Some(15 * U), "{:?}, row {}", layout, y);
+            }
+        }
+    }
+}
@@ -4,18 +4,76 @@ use std::{
     time::{Duration, Instant},
 };
 
+use serde::Serialize;
 use softbuffer::GraphicsContext;
 use unicode_width::UnicodeWidthStr;
 use winit::{
-    event::{DeviceEvent, ElementState, Event, Ime, KeyEvent, MouseButton, WindowEvent},
+    dpi::LogicalPosition,
+    event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    keyboard::{Key, KeyCode, ModifiersState},
+    keyboard::{Key, KeyCode},
     window::WindowBuilder,
 };
 
+use crate::{
+    anomaly::AnomalyDetector,
+    checklist::Checklist,
+    chords::ChordDetector,
+    cli::{Options, OutputFormat},
+    config::Config,
+    dead_keys::DeadKeyTracker,
+    expect::Expectations,
+    modifiers::ModifierChecker,
+    pairing::Pairer,
+    record::{KeyEventRecord, RecordReader, RecordedEvent, Recorder, Settings},
+    session::{RepeatMode, Session, TableTimeout},
+    timing::KeyTimings,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    canvas::Canvas,
+    delimited::{CsvTablePrinter, TsvTablePrinter},
+    heatmap::KeyboardHeatmap,
+    json::JsonTablePrinter,
+    window_table::WindowTablePrinter,
+};
+
+mod anomaly;
+#[cfg(not(target_arch = "wasm32"))]
+mod canvas;
+mod checklist;
+mod chords;
+mod cli;
+mod config;
+mod dead_keys;
+#[cfg(not(target_arch = "wasm32"))]
+mod delimited;
+mod expect;
+#[cfg(not(target_arch = "wasm32"))]
+mod font;
+#[cfg(test)]
+mod golden;
+#[cfg(not(target_arch = "wasm32"))]
+mod heatmap;
+mod ime;
+#[cfg(not(target_arch = "wasm32"))]
+mod json;
+mod layout;
+mod modifiers;
+mod pairing;
+mod record;
+#[cfg(not(target_arch = "wasm32"))]
+mod replay;
+mod session;
+mod timing;
+#[cfg(not(target_arch = "wasm32"))]
+mod window_table;
+
 #[allow(dead_code)]
 mod column {
     pub const NUMBER: &str = "Number";
+    pub const TIME: &str = "Time";
+    pub const DELTA: &str = "Δ";
     pub const KIND: &str = "Kind";
     pub const SYNTH: &str = "Synth";
     pub const STATE: &str = "State";
@@ -27,6 +85,54 @@ mod column {
     pub const KEY_NO_MOD: &str = "Key (no modifiers)";
     pub const TEXT_ALL_MODS: &str = "Text (all modifiers)";
     pub const SCAN_CODE: &str = "Scancode";
+    pub const MISMATCH: &str = "Mismatch";
+    pub const ANOMALY: &str = "Anomaly";
+    pub const PAIR: &str = "Pair";
+    pub const MOD_CHECK: &str = "Modifier check";
+    pub const TIMING: &str = "Timing";
+    pub const REPEATS: &str = "Repeats";
+    pub const PREEDIT: &str = "Preedit";
+    pub const COMMIT: &str = "Commit";
+    pub const COMPOSED: &str = "Composed";
+    pub const DEAD_KEYS: &str = "Dead keys";
+
+    /// Every column along with the name of its constant.
+    pub const ALL: &[(&str, &str)] = &[
+        ("NUMBER", NUMBER),
+        ("TIME", TIME),
+        ("DELTA", DELTA),
+        ("KIND", KIND),
+        ("SYNTH", SYNTH),
+        ("STATE", STATE),
+        ("KEY_CODE", KEY_CODE),
+        ("KEY", KEY),
+        ("LOCATION", LOCATION),
+        ("TEXT", TEXT),
+        ("MODIFIERS", MODIFIERS),
+        ("KEY_NO_MOD", KEY_NO_MOD),
+        ("TEXT_ALL_MODS", TEXT_ALL_MODS),
+        ("SCAN_CODE", SCAN_CODE),
+        ("MISMATCH", MISMATCH),
+        ("ANOMALY", ANOMALY),
+        ("PAIR", PAIR),
+        ("MOD_CHECK", MOD_CHECK),
+        ("TIMING", TIMING),
+        ("REPEATS", REPEATS),
+        ("PREEDIT", PREEDIT),
+        ("COMMIT", COMMIT),
+        ("COMPOSED", COMPOSED),
+        ("DEAD_KEYS", DEAD_KEYS),
+    ];
+
+    /// Looks up a column by its header or the name of its constant, ignoring
+    /// case.
+    pub fn from_name(name: &str) -> Option<&'static str> {
+        ALL.iter()
+            .find(|(constant, header)| {
+                constant.eq_ignore_ascii_case(name) || header.eq_ignore_ascii_case(name)
+            })
+            .map(|(_, header)| *header)
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -46,39 +152,167 @@ const TABLE_TIMEOUT: Duration = Duration::from_secs(5);
 fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     simple_logger::SimpleLogger::new().init().unwrap();
+    let options = Options::from_args();
+    let config = Config::load(options.config.as_deref()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+
+    let expectations = options.expect.as_deref().map(|path| {
+        Expectations::load(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        })
+    });
+
+    let replay_reader = options.replay.as_ref().map(|path| {
+        RecordReader::open(path).unwrap_or_else(|err| {
+            eprintln!("Failed to replay {}: {}", path.display(), err);
+            std::process::exit(1);
+        })
+    });
+
+    // A replay uses the settings of the recorded session, unless they are
+    // given on the command line.
+    let settings = replay_reader
+        .as_ref()
+        .and_then(RecordReader::settings)
+        .cloned()
+        .unwrap_or_else(|| Settings {
+            manual_mode: config.manual_mode,
+            split: config.split.unwrap_or_default(),
+            table_timeout: config.table_timeout.unwrap_or(TABLE_TIMEOUT),
+            repeats: config.repeats.unwrap_or_default(),
+            ime_allowed: config.ime.allowed,
+        });
+    let settings = Settings {
+        manual_mode: options.manual || settings.manual_mode,
+        split: options.split.unwrap_or(settings.split),
+        table_timeout: options.table_timeout.unwrap_or(settings.table_timeout),
+        repeats: options.repeats.unwrap_or(settings.repeats),
+        ime_allowed: settings.ime_allowed,
+    };
+
+    let mut table = build_table();
+    if expectations.is_some() {
+        if let Some(column) = table.column_mut(column::MISMATCH) {
+            column.enabled = true;
+        }
+    }
+    if options.anomalies {
+        if let Some(column) = table.column_mut(column::ANOMALY) {
+            column.enabled = true;
+        }
+    }
+    if options.pair {
+        if let Some(column) = table.column_mut(column::PAIR) {
+            column.enabled = true;
+        }
+    }
+    if options.check_modifiers {
+        if let Some(column) = table.column_mut(column::MOD_CHECK) {
+            column.enabled = true;
+        }
+    }
+    if settings.repeats == RepeatMode::Detailed {
+        if let Some(column) = table.column_mut(column::REPEATS) {
+            column.enabled = true;
+        }
+    }
+    if options.timing {
+        for header in [column::TIME, column::DELTA, column::TIMING].iter() {
+            if let Some(column) = table.column_mut(header) {
+                column.enabled = true;
+            }
+        }
+    }
+    if options.ime {
+        for header in [column::PREEDIT, column::COMMIT, column::COMPOSED].iter() {
+            if let Some(column) = table.column_mut(header) {
+                column.enabled = true;
+            }
+        }
+    }
+    if options.dead_keys {
+        if let Some(column) = table.column_mut(column::DEAD_KEYS) {
+            column.enabled = true;
+        }
+    }
+    config.table.apply(&mut table);
+    options.column_options.apply(&mut table);
+
+    let start = Instant::now();
+    let mut session = Session::new(table, start);
+    session.set_split_policy(settings.split);
+    session.set_repeat_mode(settings.repeats);
+    session.set_table_timeout(settings.table_timeout);
+    session.set_manual_mode(settings.manual_mode);
+    session.set_key_modifiers(options.key_modifiers);
+    session.set_ime_allowed(settings.ime_allowed);
+    if let Some(expectations) = expectations {
+        session.set_expectations(expectations);
+    }
+    if options.anomalies {
+        session.set_anomaly_detector(AnomalyDetector::new());
+    }
+    if options.pair {
+        session.set_pairer(Pairer::new());
+    }
+    if options.check_modifiers {
+        session.set_modifier_checker(ModifierChecker::new());
+    }
+    if options.dead_keys {
+        session.set_dead_key_tracker(DeadKeyTracker::new());
+    }
+    if options.timing {
+        session.set_key_timings(KeyTimings::new());
+    }
+
+    let format = options.format.or(config.format).unwrap_or_default();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(reader) = replay_reader {
+        let stdout = std::io::stdout();
+        let out = stdout.lock();
+        let mut table_printer: Box<dyn TablePrinter> = match format {
+            OutputFormat::Markdown => Box::new(MarkdownTablePrinter::new(out)),
+            OutputFormat::Csv => Box::new(CsvTablePrinter::new(out)),
+            OutputFormat::Tsv => Box::new(TsvTablePrinter::new(out)),
+            OutputFormat::Json => Box::new(JsonTablePrinter::new(out)),
+        };
+        let result = replay::replay(reader, session, &mut *table_printer);
+        if let Err(err) = result {
+            let path = options.replay.as_ref().unwrap();
+            eprintln!("Failed to replay {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
 
-    let base_window_title = "A fantastic window!";
+    let base_window_title = config
+        .window_title
+        .clone()
+        .unwrap_or_else(|| "A fantastic window!".to_string());
+    let mut checklist = options.checklist.map(Checklist::new);
     let window = WindowBuilder::new()
-        .with_title(base_window_title)
+        .with_title(window_title(
+            &base_window_title,
+            session.manual_mode(),
+            checklist.as_ref().and_then(Checklist::prompt),
+        ))
         .with_resizable(false)
         .build(&event_loop)
         .unwrap();
     let mut graphics_context = unsafe { GraphicsContext::new(&window, &window) }.unwrap();
-
-    #[rustfmt::skip]
-    let table = {
-        let mut table = Table::new();
-        table.add_column(TableColumn { header: column::NUMBER       , normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::KIND         , normal_width: 6 , extended_width: 0 , use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::SYNTH        , normal_width: 5 , extended_width: 0 , use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::STATE        , normal_width: 8 , extended_width: 0 , use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::KEY_CODE     , normal_width: 20, extended_width: 37, use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::KEY          , normal_width: 25, extended_width: 42, use_extended_width: true , enabled: true , });
-        table.add_column(TableColumn { header: column::LOCATION     , normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::TEXT         , normal_width: 12, extended_width: 0 , use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::MODIFIERS    , normal_width: 11, extended_width: 11, use_extended_width: false, enabled: true , });
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-        table.add_column(TableColumn { header: column::KEY_NO_MOD   , normal_width: 25, extended_width: 42, use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::TEXT_ALL_MODS, normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
-        table.add_column(TableColumn { header: column::SCAN_CODE    , normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: false, });
-        }
-        table
-    };
+    let [ime_x, ime_y] = config.ime.position;
+    let ime_position = LogicalPosition::new(ime_x, ime_y);
+    window.set_ime_allowed(session.ime_allowed());
+    window.set_ime_position(ime_position);
 
     #[cfg(target_arch = "wasm32")]
-    let mut table_printer = {
+    let mut table_printer: Box<dyn TablePrinter> = {
         use winit::platform::web::WindowExtWebSys;
 
         let canvas = optional_gl.window().canvas();
@@ -90,25 +324,47 @@ fn main() {
         body.append_child(&canvas)
             .expect("Append canvas to HTML body");
 
-        HtmlTablePrinter::new(document, &body, &table)
+        Box::new(HtmlTablePrinter::new(document, &body, session.table()))
     };
 
     #[cfg(not(target_arch = "wasm32"))]
-    let mut table_printer = StdoutTablePrinter::new();
-
-    let mut raw_keys_pressed = HashMap::new();
-    let mut repeated_keys = HashMap::new();
-
-    let mut focused = true;
-    let mut event_number = 0u16;
-    let mut pressed_count = 0i32;
-    let mut modifiers = Default::default();
-    let mut manual_mode = false;
+    let mut table_printer: Box<dyn TablePrinter> = match format {
+        OutputFormat::Markdown => Box::new(StdoutTablePrinter::new()),
+        OutputFormat::Csv => Box::new(CsvTablePrinter::new(std::io::stdout())),
+        OutputFormat::Tsv => Box::new(TsvTablePrinter::new(std::io::stdout())),
+        OutputFormat::Json => Box::new(JsonTablePrinter::new(std::io::stdout())),
+    };
 
-    table_printer.begin_new_table(&table);
+    // With a checklist, only its mapping table is printed, so that it can be
+    // used as a reference for `--expect`. The events are still drawn into the
+    // window.
+    let print_events = checklist.is_none() || cfg!(target_arch = "wasm32");
+    // The countdown would end up in the data of the other formats.
+    let print_countdown = print_events && format == OutputFormat::Markdown;
+    if print_events {
+        table_printer.begin_new_table(session.table());
+    }
 
-    let mut last_change = Instant::now();
-    let mut skip_timeout = false;
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut window_printer = WindowTablePrinter::new();
+    #[cfg(not(target_arch = "wasm32"))]
+    window_printer.begin_new_table(session.table());
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut heatmap = KeyboardHeatmap::new(
+        options
+            .keyboard
+            .or(options.checklist)
+            .or(config.keyboard)
+            .unwrap_or_default(),
+    );
+
+    let mut chord_detector = ChordDetector::new(&config.keys);
+    let mut recorder = options.record.as_ref().map(|path| {
+        Recorder::create(path, start, &settings).unwrap_or_else(|err| {
+            eprintln!("Failed to create {}: {}", path.display(), err);
+            std::process::exit(1);
+        })
+    });
     let mut size = window.inner_size();
     let mut screen_buf: Vec<u32> = iter::repeat(u32::MAX)
         .take(size.width as usize * size.height as usize)
@@ -117,229 +373,72 @@ fn main() {
 
     event_loop.run(move |event, _, control_flow| {
         let now = Instant::now();
-        *control_flow = ControlFlow::Poll;
 
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::Focused(focus),
-                ..
-            } => {
-                if event_number > 0 {
-                    table
-                        .print_table_line()
-                        .column(column::NUMBER, event_number)
-                        .column(column::KIND, "Focus")
-                        .column(column::STATE, if focus { "Received" } else { "Lost" })
-                        .print(&mut table_printer);
-                    event_number += 1;
+        let recorded = RecordedEvent::from_event(&event, &config.mouse)
+            .and_then(|recorded| chord_detector.handle_event(recorded));
+        if let Some(recorded) = recorded {
+            if let Some(recorder) = recorder.as_mut() {
+                if let Err(err) = recorder.record(now, session.modifiers(), recorded.clone()) {
+                    log::error!("Failed to record event: {}", err);
                 }
-                focused = focus;
-                last_change = now;
             }
-            Event::WindowEvent {
-                event:
-                    WindowEvent::KeyboardInput {
-                        event,
-                        is_synthetic,
-                        ..
-                    },
-                ..
-            } => {
-                let table = table
-                    .print_table_line()
-                    .column(column::NUMBER, event_number)
-                    .column(column::KIND, "Window")
-                    .column(column::SYNTH, is_synthetic)
-                    .column_with(column::KEY_CODE, || key_code_to_string(&event.physical_key))
-                    .column_with(column::KEY, || key_to_string(&event.logical_key))
-                    .column_with(column::LOCATION, || format!("{:?}", event.location))
-                    .column_with(column::TEXT, || {
-                        event
-                            .text
-                            .as_ref()
-                            .map(nice_text)
-                            .unwrap_or_else(|| "".to_string())
-                    })
-                    .column_with(column::KEY_NO_MOD, || key_without_modifiers(&event))
-                    .column_with(column::TEXT_ALL_MODS, || text_with_all_modifiers(&event));
-
-                if !event.repeat {
-                    table
-                        .column_with(column::STATE, || format!("{:?}", event.state))
-                        .print(&mut table_printer);
-
-                    event_number += 1;
-
-                    match event.state {
-                        ElementState::Pressed => pressed_count += 1,
-                        ElementState::Released => {
-                            repeated_keys.remove(&event.physical_key);
-                            pressed_count -= 1
-                        }
-                    }
-                } else {
-                    let repeat_count = { repeated_keys.entry(event.physical_key).or_insert(1) };
-                    if *repeat_count == 1 {
-                        event_number += 1;
-                    }
-                    table
-                        .column_with(column::STATE, || format!("Rpt {:>4}", repeat_count))
-                        .update(&mut table_printer);
-                    *repeat_count += 1;
-                }
 
-                last_change = now;
+            match recorded {
+                RecordedEvent::DeadKeyReset => window.reset_dead_keys(),
+                RecordedEvent::ImePosition => window.set_ime_position(ime_position),
+                _ => {}
             }
-            Event::DeviceEvent {
-                event: DeviceEvent::Key(event),
-                ..
-            } => {
-                if focused || pressed_count > 0 {
-                    let repeat_count = match event.state {
-                        ElementState::Pressed => Some(
-                            raw_keys_pressed
-                                .entry(event.physical_key.clone())
-                                .or_insert_with(|| {
-                                    pressed_count += 1;
-                                    0
-                                }),
-                        ),
-                        ElementState::Released => {
-                            if raw_keys_pressed.remove(&event.physical_key).is_some() {
-                                pressed_count -= 1;
-                            }
-                            None
-                        }
-                    };
-
-                    let table = table
-                        .print_table_line()
-                        .column(column::NUMBER, event_number)
-                        .column(column::KIND, "Device")
-                        .column_with(column::KEY_CODE, || key_code_to_string(&event.physical_key));
-
-                    let print_normal = if let Some(repeat_count) = repeat_count {
-                        if *repeat_count > 0 {
-                            table
-                                .clone() // TODO: Get rid of this clone
-                                .column_with(column::STATE, || format!("Rpt {:>4}", repeat_count))
-                                .update(&mut table_printer);
-                            false
-                        } else {
-                            true
-                        }
-                    } else {
-                        true
-                    };
-                    if print_normal {
-                        table
-                            .column_with(column::STATE, || format!("{:?}", event.state))
-                            .print(&mut table_printer);
-                        event_number += 1;
-                    }
-                }
 
-                last_change = now;
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                heatmap.handle_event(&recorded);
+                window.request_redraw();
             }
-            Event::WindowEvent {
-                event: WindowEvent::ModifiersChanged(new_modifiers),
-                ..
-            } => {
-                modifiers = new_modifiers;
-                if !modifiers.is_empty() || event_number != 0 {
-                    table
-                        .print_table_line()
-                        .column(column::NUMBER, event_number)
-                        .column(column::KIND, "ModC")
-                        .column_with(column::MODIFIERS, || format_modifiers(modifiers))
-                        .print(&mut table_printer);
-
-                    event_number += 1;
-                }
 
-                last_change = now;
-            }
-            Event::WindowEvent {
-                event: WindowEvent::Ime(ime),
-                ..
-            } => {
-                // TODO: Print this in a better way...
-                let mut row = table
-                    .print_table_line()
-                    .column(column::NUMBER, event_number)
-                    .column(column::KIND, "IME")
-                    .column(
-                        column::STATE,
-                        match ime {
-                            Ime::Enabled => "Enabled",
-                            Ime::Preedit(_, _) => "Preedit",
-                            Ime::Commit(_) => "Commit",
-                            Ime::Disabled => "Disabled",
-                        },
-                    );
-                match ime {
-                    // TODO: Print preedit position?
-                    Ime::Preedit(text, _) | Ime::Commit(text) => {
-                        row = row.column_with(column::TEXT, || format!("{:?}", text));
+            let mut title_changed = false;
+            let mut skip_session = false;
+            if let Some(checklist) = checklist.as_mut().filter(|c| !c.is_finished()) {
+                // The table control button skips keys until the checklist is done.
+                skip_session = matches!(recorded, RecordedEvent::TableControl);
+                title_changed = checklist.handle_event(&recorded);
+                if checklist.is_finished() {
+                    checklist.print(&mut *table_printer);
+                    if print_events {
+                        table_printer.begin_new_table(session.table());
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        checklist.print(&mut window_printer);
+                        window_printer.begin_new_table(session.table());
                     }
-                    Ime::Enabled | Ime::Disabled => {}
                 }
-                row.print(&mut table_printer);
-
-                event_number += 1;
-
-                last_change = now;
             }
-            Event::WindowEvent {
-                event:
-                    WindowEvent::MouseInput {
-                        state: ElementState::Pressed,
-                        button,
-                        ..
-                    },
-                ..
-            } => match button {
-                MouseButton::Middle => {
-                    if manual_mode {
-                        if event_number == 0 {
-                            manual_mode = false;
-                            // TODO: Move this elsewhere?
-                            window.set_title(base_window_title);
-                        } else {
-                            table_printer.begin_new_table(&table);
-                            event_number = 0;
-                            pressed_count = 0;
-                            raw_keys_pressed.clear();
-                            repeated_keys.clear();
-                            modifiers = Default::default();
-                        }
-                    } else {
-                        if event_number == 0 {
-                            manual_mode = true;
-                            // TODO: Move this elsewhere?
-                            window.set_title(&format!("{} - Manual Mode", base_window_title));
-                        } else {
-                            pressed_count = 0;
-                            skip_timeout = true;
-                            modifiers = Default::default();
-                        }
-                    }
 
-                    last_change = now;
-                }
-                MouseButton::Right => {
-                    window.reset_dead_keys();
-                    table
-                        .print_table_line()
-                        .column(column::NUMBER, event_number)
-                        .column(column::KIND, "DeadRST")
-                        .print(&mut table_printer);
-                    event_number += 1;
-
-                    last_change = now;
+            let was_manual_mode = session.manual_mode();
+            let was_ime_allowed = session.ime_allowed();
+            if !skip_session {
+                for output in session.handle_event(now, &recorded) {
+                    if print_events {
+                        output.print(session.table(), &mut *table_printer);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    output.print(session.table(), &mut window_printer);
+                    window.request_redraw();
                 }
-                _ => {}
-            },
+            }
+            if session.ime_allowed() != was_ime_allowed {
+                window.set_ime_allowed(session.ime_allowed());
+            }
+            if title_changed || session.manual_mode() != was_manual_mode {
+                window.set_title(&window_title(
+                    &base_window_title,
+                    session.manual_mode(),
+                    checklist.as_ref().and_then(Checklist::prompt),
+                ));
+            }
+        }
+
+        match event {
             Event::WindowEvent {
                 event: WindowEvent::Resized(new_size),
                 ..
@@ -347,16 +446,40 @@ fn main() {
                 let new_area = new_size.width as usize * new_size.height as usize;
                 screen_buf.resize_with(new_area, || u32::MAX);
                 size = new_size;
+                window.request_redraw();
             }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
-                if !manual_mode {
+                if !session.manual_mode() {
                     *control_flow = ControlFlow::Exit
                 }
             }
+            Event::LoopDestroyed => {
+                if print_events {
+                    for output in session.finish() {
+                        output.print(session.table(), &mut *table_printer);
+                    }
+                }
+                // Print what the checklist got so far.
+                if let Some(checklist) = checklist.as_ref().filter(|c| !c.is_finished()) {
+                    checklist.print(&mut *table_printer);
+                }
+                table_printer.finish();
+            }
             Event::RedrawRequested(_) => {
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    let mut canvas = Canvas::new(
+                        &mut screen_buf,
+                        size.width as usize,
+                        size.height as usize,
+                        window.scale_factor().round() as usize,
+                    );
+                    let heatmap_top = heatmap.draw(&mut canvas);
+                    window_printer.draw(&mut canvas, heatmap_top);
+                }
                 graphics_context.set_buffer(
                     &screen_buf,
                     size.width.min(u16::MAX.into()) as u16,
@@ -366,27 +489,83 @@ fn main() {
             _ => (),
         }
 
-        if !manual_mode {
-            if pressed_count == 0 && modifiers.is_empty() {
-                if event_number != 0 {
-                    if last_change + TABLE_TIMEOUT <= now || skip_timeout {
-                        print!("\r{:30}", "");
-                        table_printer.begin_new_table(&table);
-                        event_number = 0;
-                        skip_timeout = false;
-                        *control_flow = ControlFlow::Wait;
-                    } else {
-                        print!(
-                            "\rTable finishes in {}s",
-                            (TABLE_TIMEOUT - now.duration_since(last_change)).as_secs()
-                        );
-                    }
-                }
+        if let TableTimeout::Pending(remaining) = session.table_timeout(now) {
+            if print_countdown {
+                print!("\rTable finishes in {}s", remaining.as_secs());
             }
         }
+        let output = session.tick(now);
+        if !output.is_empty() && print_countdown {
+            print!("\r{:30}", "");
+        }
+        for output in output {
+            if print_events {
+                output.print(session.table(), &mut *table_printer);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            output.print(session.table(), &mut window_printer);
+            window.request_redraw();
+        }
+
+        // Only wake up without an event to finish the table or to update its
+        // countdown.
+        *control_flow = match session.table_timeout(now) {
+            TableTimeout::Pending(remaining) if print_countdown => {
+                let next_second = Duration::from_nanos(remaining.subsec_nanos().into());
+                ControlFlow::WaitUntil(now + next_second)
+            }
+            TableTimeout::Pending(remaining) => ControlFlow::WaitUntil(now + remaining),
+            TableTimeout::Expired => ControlFlow::Poll,
+            TableTimeout::Inactive => ControlFlow::Wait,
+        };
     });
 }
 
+fn window_title(base_window_title: &str, manual_mode: bool, prompt: Option<String>) -> String {
+    let mut title = base_window_title.to_string();
+    if manual_mode {
+        title.push_str(" - Manual Mode");
+    }
+    if let Some(prompt) = prompt {
+        title.push_str(" - ");
+        title.push_str(&prompt);
+    }
+    title
+}
+
+#[rustfmt::skip]
+fn build_table() -> Table {
+    let mut table = Table::new();
+    table.add_column(TableColumn { header: column::NUMBER       , normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::TIME         , normal_width: 9 , extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::DELTA        , normal_width: 9 , extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::KIND         , normal_width: 6 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::SYNTH        , normal_width: 5 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::STATE        , normal_width: 8 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::KEY_CODE     , normal_width: 20, extended_width: 37, use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::KEY          , normal_width: 25, extended_width: 42, use_extended_width: true , enabled: true , });
+    table.add_column(TableColumn { header: column::LOCATION     , normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::TEXT         , normal_width: 12, extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::MODIFIERS    , normal_width: 11, extended_width: 11, use_extended_width: false, enabled: true , });
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+    table.add_column(TableColumn { header: column::KEY_NO_MOD   , normal_width: 25, extended_width: 42, use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::TEXT_ALL_MODS, normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    table.add_column(TableColumn { header: column::SCAN_CODE    , normal_width: 0 , extended_width: 0 , use_extended_width: false, enabled: true , });
+    }
+    table.add_column(TableColumn { header: column::MISMATCH     , normal_width: 20, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::ANOMALY      , normal_width: 21, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::PAIR         , normal_width: 17, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::MOD_CHECK    , normal_width: 20, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::TIMING       , normal_width: 20, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::REPEATS      , normal_width: 20, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::PREEDIT      , normal_width: 16, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::COMMIT       , normal_width: 12, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::COMPOSED     , normal_width: 16, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table.add_column(TableColumn { header: column::DEAD_KEYS    , normal_width: 20, extended_width: 0 , use_extended_width: false, enabled: false, });
+    table
+}
+
 fn key_to_string(key: &Key) -> String {
     match key {
         Key::Unidentified(native_key) => format!("Unidentified({:?})", native_key),
@@ -401,29 +580,26 @@ fn key_code_to_string(code: &KeyCode) -> String {
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn key_without_modifiers(event: &KeyEvent) -> String {
-    use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
-    format!("{:?}", event.key_without_modifiers())
+fn scan_code_to_string(scan_code: Option<u32>) -> String {
+    scan_code
+        .map(|scan_code| format!("{:#04x}", scan_code))
+        .unwrap_or_default()
 }
 
-#[cfg(target_arch = "wasm32")]
-fn key_without_modifiers(_: &KeyEvent) -> &'static str {
-    ""
+fn key_without_modifiers(event: &KeyEventRecord) -> String {
+    event
+        .key_without_modifiers
+        .as_ref()
+        .map(|key| format!("{:?}", key))
+        .unwrap_or_default()
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn text_with_all_modifiers(event: &KeyEvent) -> String {
-    use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+fn text_with_all_modifiers(event: &KeyEventRecord) -> String {
     event
-        .text_with_all_modifiers()
+        .text_with_all_modifiers
+        .as_ref()
         .map(nice_text)
-        .unwrap_or_else(String::new)
-}
-
-#[cfg(target_arch = "wasm32")]
-fn text_with_all_modifiers(_: &KeyEvent) -> &'static str {
-    ""
+        .unwrap_or_default()
 }
 
 fn nice_text<S>(text: S) -> String
@@ -438,34 +614,6 @@ where
     }
 }
 
-fn format_modifiers(modifiers: ModifiersState) -> String {
-    let mut string = String::with_capacity(modifiers.bits().count_ones() as usize * 3);
-
-    if modifiers.contains(ModifiersState::ALT) {
-        string.push_str("AL");
-    }
-    if modifiers.contains(ModifiersState::CONTROL) {
-        if !string.is_empty() {
-            string.push('|');
-        }
-        string.push_str("CO");
-    }
-    if modifiers.contains(ModifiersState::SHIFT) {
-        if !string.is_empty() {
-            string.push('|');
-        }
-        string.push_str("SH");
-    }
-    if modifiers.contains(ModifiersState::SUPER) {
-        if !string.is_empty() {
-            string.push('|');
-        }
-        string.push_str("SU");
-    }
-
-    string
-}
-
 struct Table {
     columns: Vec<TableColumn>,
 }
@@ -481,6 +629,28 @@ impl Table {
         self.columns.push(column);
     }
 
+    fn column_mut(&mut self, header: &str) -> Option<&mut TableColumn> {
+        self.columns.iter_mut().find(|col| col.header == header)
+    }
+
+    /// Enables the given columns and moves them to the front, in the given
+    /// order. The other columns are disabled, but kept so they can be shown
+    /// again later.
+    fn select_columns(&mut self, headers: &[&str]) {
+        let mut columns = std::mem::take(&mut self.columns);
+        for header in headers {
+            if let Some(index) = columns.iter().position(|col| col.header == *header) {
+                let mut column = columns.remove(index);
+                column.enabled = true;
+                self.columns.push(column);
+            }
+        }
+        for mut column in columns {
+            column.enabled = false;
+            self.columns.push(column);
+        }
+    }
+
     fn print_table_line(&self) -> RowBuilder<'_> {
         RowBuilder::new(self)
     }
@@ -505,10 +675,10 @@ impl TableColumn {
     }
 }
 
-#[derive(Clone)]
 struct RowBuilder<'a> {
     table: &'a Table,
-    column_values: HashMap<String, String>,
+    column_values: HashMap<String, Cell>,
+    highlighted: bool,
 }
 
 impl<'a> RowBuilder<'a> {
@@ -516,17 +686,17 @@ impl<'a> RowBuilder<'a> {
         Self {
             table,
             column_values: HashMap::new(),
+            highlighted: false,
         }
     }
 
     fn column<T>(mut self, column: &str, value: T) -> Self
     where
-        T: ToString,
+        T: Into<Cell>,
     {
         if let Some(col) = self.table.columns.iter().find(|col| col.header == column) {
             if col.enabled {
-                self.column_values
-                    .insert(column.to_string(), value.to_string());
+                self.column_values.insert(column.to_string(), value.into());
             }
         }
         self
@@ -534,32 +704,104 @@ impl<'a> RowBuilder<'a> {
 
     fn column_with<F: FnOnce() -> T, T>(mut self, column: &str, f: F) -> Self
     where
-        T: ToString,
+        T: Into<Cell>,
     {
         if let Some(col) = self.table.columns.iter().find(|col| col.header == column) {
             if col.enabled {
-                self.column_values
-                    .insert(column.to_string(), f().to_string());
+                self.column_values.insert(column.to_string(), f().into());
             }
         }
         self
     }
 
-    fn print<P: TablePrinter>(self, printer: &mut P) {
-        printer.print_row(self)
+    /// Makes the row stand out, where the printer can do that.
+    fn highlight(mut self, highlighted: bool) -> Self {
+        self.highlighted |= highlighted;
+        self
     }
 
-    fn update<P: TablePrinter>(self, printer: &mut P) {
-        printer.update_row(self)
+    fn finish(self) -> Row {
+        Row {
+            column_values: self.column_values,
+            highlighted: self.highlighted,
+        }
+    }
+}
+
+/// The value of a table cell.
+///
+/// `text` is what the tables show, while `value` keeps the type of the value
+/// for the JSON output.
+#[derive(Clone, Debug, PartialEq)]
+struct Cell {
+    text: String,
+    value: serde_json::Value,
+}
+
+impl Cell {
+    /// A cell that shows `text`, but whose value is `value`.
+    fn typed<T: Serialize + ?Sized>(text: String, value: &T) -> Self {
+        Self {
+            text,
+            value: serde_json::to_value(value).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Self {
+            value: serde_json::Value::String(text.clone()),
+            text,
+        }
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+impl From<bool> for Cell {
+    fn from(value: bool) -> Self {
+        Self::typed(value.to_string(), &value)
+    }
+}
+
+impl From<u16> for Cell {
+    fn from(value: u16) -> Self {
+        Self::typed(value.to_string(), &value)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Row {
+    column_values: HashMap<String, Cell>,
+    highlighted: bool,
+}
+
+impl Row {
+    fn get(&self, column: &str) -> Option<&str> {
+        self.column_values
+            .get(column)
+            .map(|cell| cell.text.as_str())
+    }
+
+    fn value(&self, column: &str) -> Option<&serde_json::Value> {
+        self.column_values.get(column).map(|cell| &cell.value)
     }
 }
 
 trait TablePrinter {
     fn begin_new_table(&mut self, table: &Table);
 
-    fn print_row(&mut self, row: RowBuilder<'_>);
+    fn print_row(&mut self, table: &Table, row: &Row);
+
+    fn update_row(&mut self, table: &Table, row: &Row);
 
-    fn update_row(&mut self, row: RowBuilder<'_>);
+    /// Called once no more rows will be printed.
+    fn finish(&mut self) {}
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -590,7 +832,7 @@ impl TablePrinter for StdoutTablePrinter {
         self.ioprinter.begin_new_table(table, &mut out);
     }
 
-    fn print_row(&mut self, row: RowBuilder<'_>) {
+    fn print_row(&mut self, table: &Table, row: &Row) {
         use std::io::{self, Write};
         let stdout = io::stdout();
         let mut out = stdout.lock();
@@ -602,12 +844,12 @@ impl TablePrinter for StdoutTablePrinter {
 
         write!(out, "\r").unwrap();
 
-        self.ioprinter.print_row(row, &mut out);
+        self.ioprinter.print_row(table, row, &mut out);
 
         write!(out, "\n").unwrap();
     }
 
-    fn update_row(&mut self, row: RowBuilder<'_>) {
+    fn update_row(&mut self, table: &Table, row: &Row) {
         use std::io::{self, Write};
         let stdout = io::stdout();
         let mut out = stdout.lock();
@@ -615,7 +857,65 @@ impl TablePrinter for StdoutTablePrinter {
         self.updating = true;
 
         write!(out, "\r").unwrap();
-        self.ioprinter.print_row(row, &mut out);
+        self.ioprinter.print_row(table, row, &mut out);
+    }
+}
+
+/// Prints plain markdown, for when the output isn't a terminal.
+///
+/// Rows that may still be updated are held back until the next row is printed.
+#[cfg(not(target_arch = "wasm32"))]
+struct MarkdownTablePrinter<W> {
+    out: W,
+    pending_row: Vec<u8>,
+    updating: bool,
+    ioprinter: IoWriteTablePrinter,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<W: std::io::Write> MarkdownTablePrinter<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out,
+            pending_row: Vec::new(),
+            updating: false,
+            ioprinter: IoWriteTablePrinter::new(),
+        }
+    }
+
+    fn flush_pending_row(&mut self) {
+        if self.updating {
+            self.out.write_all(&self.pending_row).unwrap();
+            writeln!(self.out).unwrap();
+            self.pending_row.clear();
+            self.updating = false;
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<W: std::io::Write> TablePrinter for MarkdownTablePrinter<W> {
+    fn begin_new_table(&mut self, table: &Table) {
+        self.flush_pending_row();
+        writeln!(self.out).unwrap();
+        self.ioprinter.begin_new_table(table, &mut self.out);
+    }
+
+    fn print_row(&mut self, table: &Table, row: &Row) {
+        self.flush_pending_row();
+        self.ioprinter.print_row(table, row, &mut self.out);
+        writeln!(self.out).unwrap();
+    }
+
+    fn update_row(&mut self, table: &Table, row: &Row) {
+        self.pending_row.clear();
+        self.ioprinter.print_row(table, row, &mut self.pending_row);
+        self.updating = true;
+    }
+
+    fn finish(&mut self) {
+        self.flush_pending_row();
+        self.out.flush().unwrap();
     }
 }
 
@@ -659,7 +959,7 @@ impl HtmlTablePrinter {
         let table_element = document.create_element("table").unwrap();
         let thead = document.create_element("thead").unwrap();
         let header_row = document.create_element("tr").unwrap();
-        for column in table.columns.iter() {
+        for column in table.columns.iter().filter(|column| column.enabled) {
             let header = document.create_element("th").unwrap();
             header.set_text_content(Some(&column.header));
             header_row.append_child(&header).unwrap();
@@ -720,23 +1020,23 @@ impl TablePrinter for HtmlTablePrinter {
         self.last_table = Some(details);
     }
 
-    fn print_row(&mut self, row: RowBuilder<'_>) {
+    fn print_row(&mut self, table: &Table, row: &Row) {
         let tr = self.document.create_element("tr").unwrap();
-        self.fill_row(&tr, row);
+        self.fill_row(&tr, table, row);
         self.tbody.append_child(&tr).unwrap();
         self.updating = false;
     }
 
-    fn update_row(&mut self, row: RowBuilder<'_>) {
+    fn update_row(&mut self, table: &Table, row: &Row) {
         if self.updating {
             if let Some(tr) = self.tbody.last_element_child() {
                 while let Some(last_child) = tr.last_element_child() {
                     tr.remove_child(last_child.as_ref()).unwrap();
                 }
-                self.fill_row(&tr, row)
+                self.fill_row(&tr, table, row)
             }
         } else {
-            self.print_row(row);
+            self.print_row(table, row);
             self.updating = true;
         }
     }
@@ -744,20 +1044,21 @@ impl TablePrinter for HtmlTablePrinter {
 
 #[cfg(target_arch = "wasm32")]
 impl HtmlTablePrinter {
-    fn fill_row(&mut self, tr: &web_sys::Element, row: RowBuilder<'_>) {
-        for column in row.table.columns.iter() {
+    fn fill_row(&mut self, tr: &web_sys::Element, table: &Table, row: &Row) {
+        tr.set_class_name(if row.highlighted { "highlighted" } else { "" });
+        for column in table.columns.iter() {
             if !column.enabled {
                 continue;
             }
             let td = self.document.create_element("td").unwrap();
-            if let Some(value) = row.column_values.get(&column.header) {
+            if let Some(value) = row.get(column.header) {
                 td.set_text_content(Some(value));
             }
             tr.append_child(&td).unwrap();
         }
 
         self.ioprinter
-            .print_row(row, &mut self.markdown_table_buffer);
+            .print_row(table, row, &mut self.markdown_table_buffer);
     }
 }
 
@@ -807,19 +1108,15 @@ impl IoWriteTablePrinter {
         out.flush().unwrap();
     }
 
-    fn print_row<W>(&mut self, row: RowBuilder<'_>, out: &mut W)
+    fn print_row<W>(&mut self, table: &Table, row: &Row, out: &mut W)
     where
         W: std::io::Write,
     {
-        for column in row.table.columns.iter() {
+        for column in table.columns.iter() {
             if !column.enabled {
                 continue;
             }
-            let content = row
-                .column_values
-                .get(column.header)
-                .map(AsRef::as_ref)
-                .unwrap_or("");
+            let content = row.get(column.header).unwrap_or("");
             let content_width = content.width();
             let padding = column.width().saturating_sub(content_width);
             write!(out, "| {content}{:padding$} ", "").unwrap();
@@ -0,0 +1,408 @@
+//! The modifier keys behind a [`ModifiersState`].
+//!
+//! Winit only reports which modifiers are active, not which of their keys are
+//! held. That is derived from the key events instead, from both the window
+//! and the device events, so that a modifier key that is held before the
+//! window reports its press still counts.
+//!
+//! Lock keys are toggled by every press the window sees. Their state before
+//! the first press isn't known, so they start out unlocked.
+//!
+//! [`ModifierChecker`] compares the modifiers that winit reports to the ones
+//! derived from the keys. Platforms disagree on whether `ModifiersChanged`
+//! comes before or after the event of the modifier key itself, so the check
+//! happens on the events of the other keys, by which time both should agree.
+
+use std::collections::HashSet;
+
+use serde_json::json;
+use winit::{
+    event::ElementState,
+    keyboard::{KeyCode, ModifiersState},
+};
+
+use crate::{record::RecordedEvent, Cell};
+
+/// Each modifier with its short and long name and its left and right key.
+const MODIFIERS: [(ModifiersState, &str, &str, KeyCode, KeyCode); 4] = [
+    (
+        ModifiersState::ALT,
+        "AL",
+        "Alt",
+        KeyCode::AltLeft,
+        KeyCode::AltRight,
+    ),
+    (
+        ModifiersState::CONTROL,
+        "CO",
+        "Control",
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+    ),
+    (
+        ModifiersState::SHIFT,
+        "SH",
+        "Shift",
+        KeyCode::ShiftLeft,
+        KeyCode::ShiftRight,
+    ),
+    (
+        ModifiersState::SUPER,
+        "SU",
+        "Super",
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ),
+];
+
+/// Each lock key with its short and long name.
+const LOCKS: [(KeyCode, &str, &str); 3] = [
+    (KeyCode::CapsLock, "CAPS", "CapsLock"),
+    (KeyCode::NumLock, "NUM", "NumLock"),
+    (KeyCode::ScrollLock, "SCRL", "ScrollLock"),
+];
+
+#[derive(Default)]
+pub struct ModifierKeys {
+    window_held: HashSet<KeyCode>,
+    device_held: HashSet<KeyCode>,
+    locked: HashSet<KeyCode>,
+}
+
+impl ModifierKeys {
+    pub fn handle_event(&mut self, event: &RecordedEvent) {
+        match event {
+            RecordedEvent::KeyboardInput { event, .. } => match event.state {
+                ElementState::Pressed => {
+                    let key_code = event.physical_key;
+                    let is_lock = LOCKS.iter().any(|(lock, _, _)| *lock == key_code);
+                    if is_lock && !event.repeat {
+                        if self.locked.contains(&key_code) {
+                            self.locked.remove(&key_code);
+                        } else {
+                            self.locked.insert(key_code);
+                        }
+                    }
+                    self.window_held.insert(key_code);
+                }
+                ElementState::Released => {
+                    self.window_held.remove(&event.physical_key);
+                }
+            },
+            RecordedEvent::DeviceKey {
+                physical_key,
+                state,
+                ..
+            } => match state {
+                ElementState::Pressed => {
+                    self.device_held.insert(*physical_key);
+                }
+                ElementState::Released => {
+                    self.device_held.remove(physical_key);
+                }
+            },
+            // A modifier key released in another window would keep its side in
+            // the Modifiers column and count as held for the modifier check.
+            RecordedEvent::Focused { focused: false } => {
+                self.window_held.clear();
+                self.device_held.clear();
+            }
+            // The releases of a table control chord's keys are taken out.
+            RecordedEvent::TableControl => {
+                self.window_held.clear();
+                self.device_held.clear();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_held(&self, key_code: KeyCode) -> bool {
+        self.window_held.contains(&key_code) || self.device_held.contains(&key_code)
+    }
+
+    /// The modifiers that one of the held keys belongs to.
+    pub fn modifiers(&self) -> ModifiersState {
+        MODIFIERS
+            .iter()
+            .filter(|(_, _, _, left, right)| self.is_held(*left) || self.is_held(*right))
+            .fold(ModifiersState::empty(), |modifiers, (modifier, ..)| {
+                modifiers | *modifier
+            })
+    }
+
+    /// Describes `modifiers` along with the side of each of them and the
+    /// locks, like `RAL|LSH|CAPS`.
+    ///
+    /// A modifier is prefixed with `L`, `R` or `LR` for the keys that are
+    /// held, and has no prefix if none of them are.
+    pub fn cell(&self, modifiers: ModifiersState) -> Cell {
+        let mut codes = Vec::new();
+        let mut names = Vec::new();
+        for (modifier, code, name, left, right) in MODIFIERS.iter() {
+            if !modifiers.contains(*modifier) {
+                continue;
+            }
+            match (self.is_held(*left), self.is_held(*right)) {
+                (true, true) => {
+                    codes.push(format!("LR{}", code));
+                    names.push(format!("{}Left", name));
+                    names.push(format!("{}Right", name));
+                }
+                (true, false) => {
+                    codes.push(format!("L{}", code));
+                    names.push(format!("{}Left", name));
+                }
+                (false, true) => {
+                    codes.push(format!("R{}", code));
+                    names.push(format!("{}Right", name));
+                }
+                (false, false) => {
+                    codes.push(code.to_string());
+                    names.push(name.to_string());
+                }
+            }
+        }
+        for (lock, code, name) in LOCKS.iter() {
+            if self.locked.contains(lock) {
+                codes.push(code.to_string());
+                names.push(name.to_string());
+            }
+        }
+        Cell::typed(codes.join("|"), &names)
+    }
+}
+
+/// The modifier that `key_code` belongs to, if any.
+fn modifier_of(key_code: KeyCode) -> ModifiersState {
+    MODIFIERS
+        .iter()
+        .find(|(_, _, _, left, right)| *left == key_code || *right == key_code)
+        .map_or(ModifiersState::empty(), |(modifier, ..)| *modifier)
+}
+
+/// The short names of `modifiers`, like `SH|CO`, and their long names.
+fn names(modifiers: ModifiersState) -> (String, Vec<&'static str>) {
+    let active: Vec<_> = MODIFIERS
+        .iter()
+        .filter(|(modifier, ..)| modifiers.contains(*modifier))
+        .collect();
+    let codes: Vec<_> = active.iter().map(|(_, code, ..)| *code).collect();
+    let names = active.iter().map(|(_, _, name, ..)| *name).collect();
+    (codes.join("|"), names)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Disagreement {
+    /// A modifier whose key is held, but that winit doesn't report.
+    Missing,
+    /// A modifier that winit reports, but none of whose keys are held.
+    Spurious,
+    /// A `ModifiersChanged` event that only fixed a disagreement after
+    /// another key had been seen.
+    Late,
+}
+
+impl Disagreement {
+    fn description(self) -> &'static str {
+        match self {
+            Disagreement::Missing => "Missing",
+            Disagreement::Spurious => "Spurious",
+            Disagreement::Late => "Late",
+        }
+    }
+}
+
+pub struct ModifierChecker {
+    /// The modifiers that were found missing or spurious and haven't been
+    /// fixed by a `ModifiersChanged` event yet.
+    missing: ModifiersState,
+    spurious: ModifiersState,
+    /// The disagreements in the current table.
+    disagreements: Vec<(Disagreement, ModifiersState)>,
+}
+
+impl ModifierChecker {
+    pub fn new() -> Self {
+        Self {
+            missing: ModifiersState::empty(),
+            spurious: ModifiersState::empty(),
+            disagreements: Vec::new(),
+        }
+    }
+
+    /// Returns how the modifiers that winit reported before the event
+    /// disagree with the held `keys`, which already include the event.
+    pub fn handle_event(
+        &mut self,
+        event: &RecordedEvent,
+        keys: &ModifierKeys,
+        reported: ModifiersState,
+    ) -> Option<Cell> {
+        let found = match event {
+            RecordedEvent::KeyboardInput { event, .. } if !event.repeat => {
+                let derived = keys.modifiers();
+                let own = modifier_of(event.physical_key);
+                let missing = derived - reported - own;
+                let spurious = reported - derived - own;
+                self.missing |= missing;
+                self.spurious |= spurious;
+                vec![
+                    (Disagreement::Missing, missing),
+                    (Disagreement::Spurious, spurious),
+                ]
+            }
+            RecordedEvent::ModifiersChanged { modifiers } => {
+                let late = (self.missing & *modifiers) | (self.spurious - *modifiers);
+                self.missing -= *modifiers;
+                self.spurious &= *modifiers;
+                vec![(Disagreement::Late, late)]
+            }
+            RecordedEvent::Focused { focused: false } => {
+                self.missing = ModifiersState::empty();
+                self.spurious = ModifiersState::empty();
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+
+        let found: Vec<_> = found
+            .into_iter()
+            .filter(|(_, modifiers)| !modifiers.is_empty())
+            .collect();
+        if found.is_empty() {
+            return None;
+        }
+        self.disagreements.extend(found.iter().copied());
+        Some(describe(&found))
+    }
+
+    /// Sums up the disagreements since the last summary, along with their
+    /// number.
+    pub fn summary(&mut self) -> (Cell, usize) {
+        let disagreements = std::mem::take(&mut self.disagreements);
+        if disagreements.is_empty() {
+            return (Cell::typed("No disagreements".to_string(), &json!([])), 0);
+        }
+        let cell = describe(&disagreements);
+        let text = format!("{} disagreements: {}", disagreements.len(), cell.text);
+        (Cell::typed(text, &cell.value), disagreements.len())
+    }
+}
+
+/// Describes disagreements like `Missing SH, Spurious CO`.
+fn describe(disagreements: &[(Disagreement, ModifiersState)]) -> Cell {
+    let mut text = Vec::new();
+    let mut value = Vec::new();
+    for (disagreement, modifiers) in disagreements {
+        let (codes, names) = names(*modifiers);
+        text.push(format!("{} {}", disagreement.description(), codes));
+        value.push(json!({
+            "disagreement": format!("{:?}", disagreement),
+            "modifiers": names,
+        }));
+    }
+    Cell::typed(text.join(", "), &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::key;
+
+    #[test]
+    fn sides_and_locks_are_shown() {
+        let mut keys = ModifierKeys::default();
+        let describe = |keys: &ModifierKeys, modifiers| keys.cell(modifiers).text;
+        let alt_gr = ModifiersState::CONTROL | ModifiersState::ALT;
+
+        assert_eq!(describe(&keys, ModifiersState::SHIFT), "SH");
+
+        keys.handle_event(&key(KeyCode::ControlLeft, ElementState::Pressed, false));
+        keys.handle_event(&RecordedEvent::DeviceKey {
+            physical_key: KeyCode::AltRight,
+            state: ElementState::Pressed,
+            scan_code: None,
+        });
+        assert_eq!(describe(&keys, alt_gr), "RAL|LCO");
+
+        keys.handle_event(&key(KeyCode::CapsLock, ElementState::Pressed, false));
+        keys.handle_event(&key(KeyCode::CapsLock, ElementState::Released, false));
+        keys.handle_event(&key(KeyCode::ShiftLeft, ElementState::Pressed, false));
+        keys.handle_event(&key(KeyCode::ShiftRight, ElementState::Pressed, false));
+        assert_eq!(
+            describe(&keys, alt_gr | ModifiersState::SHIFT),
+            "RAL|LCO|LRSH|CAPS"
+        );
+        assert_eq!(
+            keys.cell(ModifiersState::SHIFT).value,
+            serde_json::json!(["ShiftLeft", "ShiftRight", "CapsLock"])
+        );
+
+        keys.handle_event(&RecordedEvent::Focused { focused: false });
+        keys.handle_event(&key(KeyCode::CapsLock, ElementState::Pressed, false));
+        assert_eq!(describe(&keys, ModifiersState::CONTROL), "CO");
+    }
+
+    #[test]
+    fn reported_modifiers_are_checked_against_the_keys() {
+        let mut keys = ModifierKeys::default();
+        let mut checker = ModifierChecker::new();
+        let mut reported = ModifiersState::empty();
+        let mut check = |event: RecordedEvent| {
+            keys.handle_event(&event);
+            let check = checker.handle_event(&event, &keys, reported);
+            if let RecordedEvent::ModifiersChanged { modifiers } = event {
+                reported = modifiers;
+            }
+            check.map(|cell| cell.text)
+        };
+        let modifiers_changed = |modifiers| RecordedEvent::ModifiersChanged { modifiers };
+
+        // The modifier key itself may come before or after the change.
+        assert_eq!(
+            check(key(KeyCode::ShiftLeft, ElementState::Pressed, false)),
+            None
+        );
+        assert_eq!(check(modifiers_changed(ModifiersState::SHIFT)), None);
+        assert_eq!(
+            check(key(KeyCode::KeyA, ElementState::Pressed, false)),
+            None
+        );
+
+        assert_eq!(
+            check(key(KeyCode::ControlLeft, ElementState::Pressed, false)),
+            None
+        );
+        assert_eq!(
+            check(key(KeyCode::KeyA, ElementState::Released, false)).as_deref(),
+            Some("Missing CO")
+        );
+        assert_eq!(
+            check(modifiers_changed(
+                ModifiersState::SHIFT | ModifiersState::CONTROL
+            ))
+            .as_deref(),
+            Some("Late CO")
+        );
+
+        assert_eq!(
+            check(key(KeyCode::ShiftLeft, ElementState::Released, false)),
+            None
+        );
+        assert_eq!(check(modifiers_changed(ModifiersState::CONTROL)), None);
+        assert_eq!(
+            check(key(KeyCode::ControlLeft, ElementState::Released, false)),
+            None
+        );
+        assert_eq!(
+            check(key(KeyCode::KeyB, ElementState::Pressed, false)).as_deref(),
+            Some("Spurious CO")
+        );
+
+        assert_eq!(
+            checker.summary().0.text,
+            "3 disagreements: Missing CO, Late CO, Spurious CO"
+        );
+        assert_eq!(checker.summary().0.text, "No disagreements");
+    }
+}
@@ -0,0 +1,285 @@
+//! Pairing of device events with the window events they produced.
+//!
+//! Events are paired by key and state, in the order they arrived, if they are
+//! no further apart than [`MAX_LATENCY`]. The later event of a pair refers to
+//! the row of the earlier one. An event that doesn't get a pair in time, or by
+//! the end of its table, is marked on a row of its own. Repeats aren't paired,
+//! since the window and the device events don't repeat at the same rate.
+//!
+//! Events can't be paired across tables, since the row numbers start over.
+
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use winit::{event::ElementState, keyboard::KeyCode};
+
+use crate::{key_code_to_string, Cell};
+
+/// Events that are further apart than this aren't paired.
+pub const MAX_LATENCY: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Window,
+    Device,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Window => "Window",
+            Source::Device => "Device",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PairEvent {
+    source: Source,
+    key_code: KeyCode,
+    state: ElementState,
+    number: u16,
+    time: Instant,
+}
+
+impl PairEvent {
+    fn describe(&self) -> String {
+        format!(
+            "{} {} {} {:?}",
+            self.source.name(),
+            self.number,
+            key_code_to_string(&self.key_code),
+            self.state
+        )
+    }
+
+    /// The cell that marks the event as unpaired.
+    fn unpaired_cell(&self) -> Cell {
+        let text = format!("{} {} unpaired", self.source.name(), self.number);
+        let value = json!({
+            "source": self.source.name(),
+            "number": self.number,
+            "key_code": self.key_code,
+            "state": self.state,
+        });
+        Cell::typed(text, &value)
+    }
+}
+
+pub struct Pairer {
+    /// Events that are still waiting for their pair, oldest first.
+    pending: Vec<PairEvent>,
+    /// Events that didn't get a pair, in the current table.
+    unpaired: Vec<PairEvent>,
+    /// How much later the window events arrived than their device events, in
+    /// milliseconds, in the current table.
+    latencies: Vec<f64>,
+    /// Whether window events can have a partner at all. Without device
+    /// events, the summary would only list every window event as unpaired.
+    device_events_seen: bool,
+}
+
+impl Pairer {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            unpaired: Vec::new(),
+            latencies: Vec::new(),
+            device_events_seen: false,
+        }
+    }
+
+    /// Pairs the event in row `number` with an earlier one. Returns the pair
+    /// if there is one. Events that are too old to be paired have to be taken
+    /// out with [`Pairer::expire`] first.
+    pub fn pair(
+        &mut self,
+        now: Instant,
+        source: Source,
+        key_code: KeyCode,
+        state: ElementState,
+        number: u16,
+    ) -> Option<Cell> {
+        if source == Source::Device {
+            self.device_events_seen = true;
+        }
+
+        let event = PairEvent {
+            source,
+            key_code,
+            state,
+            number,
+            time: now,
+        };
+        let index = self.pending.iter().position(|pending| {
+            pending.source != source && pending.key_code == key_code && pending.state == state
+        });
+        let index = match index {
+            Some(index) => index,
+            None => {
+                self.pending.push(event);
+                return None;
+            }
+        };
+
+        let other = self.pending.remove(index);
+        let latency = now.duration_since(other.time).as_secs_f64() * 1000.0;
+        self.latencies.push(match source {
+            Source::Window => latency,
+            Source::Device => -latency,
+        });
+        let text = format!("{} {} +{:.1}ms", other.source.name(), other.number, latency);
+        let value = json!({
+            "source": other.source.name(),
+            "number": other.number,
+            "latency_ms": latency,
+        });
+        Some(Cell::typed(text, &value))
+    }
+
+    /// Gives up on the events that waited too long for their pair. Returns the
+    /// cells that mark them as unpaired.
+    pub fn expire(&mut self, now: Instant) -> Vec<Cell> {
+        let (expired, pending): (Vec<_>, Vec<_>) = self
+            .pending
+            .drain(..)
+            .partition(|pending| pending.time + MAX_LATENCY < now);
+        self.pending = pending;
+        self.give_up(expired)
+    }
+
+    /// Gives up on all events that are still waiting for their pair, at the
+    /// end of a table.
+    pub fn expire_all(&mut self) -> Vec<Cell> {
+        let expired = std::mem::take(&mut self.pending);
+        self.give_up(expired)
+    }
+
+    fn give_up(&mut self, expired: Vec<PairEvent>) -> Vec<Cell> {
+        // Without device events, every window event would be marked.
+        let cells = if self.device_events_seen {
+            expired.iter().map(PairEvent::unpaired_cell).collect()
+        } else {
+            Vec::new()
+        };
+        self.unpaired.extend(expired);
+        cells
+    }
+
+    /// Sums up the pairs since the last summary, along with the number of
+    /// events that didn't get one. Returns `None` if there were no events.
+    ///
+    /// Events that are still waiting for their pair count as unpaired.
+    pub fn summary(&mut self) -> Option<(Cell, usize)> {
+        self.expire_all();
+        let unpaired = std::mem::take(&mut self.unpaired);
+        let latencies = std::mem::take(&mut self.latencies);
+        if !self.device_events_seen {
+            // The platform doesn't have device events, so nothing pairs.
+            return None;
+        }
+        if latencies.is_empty() && unpaired.is_empty() {
+            return None;
+        }
+
+        let mut text = format!("{} pairs", latencies.len());
+        if !latencies.is_empty() {
+            let average = latencies.iter().sum::<f64>() / latencies.len() as f64;
+            let min = latencies.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = latencies.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            text.push_str(&format!(
+                ", window latency {:.1}ms on average ({:.1} to {:.1}ms)",
+                average, min, max
+            ));
+        }
+        if !unpaired.is_empty() {
+            let list: Vec<_> = unpaired.iter().map(PairEvent::describe).collect();
+            text.push_str(&format!(
+                ", {} unpaired: {}",
+                unpaired.len(),
+                list.join(", ")
+            ));
+        }
+
+        let value = json!({
+            "latencies_ms": latencies,
+            "unpaired": unpaired.iter().map(|event| json!({
+                "source": event.source.name(),
+                "number": event.number,
+                "key_code": event.key_code,
+                "state": event.state,
+            })).collect::<Vec<_>>(),
+        });
+        Some((Cell::typed(text, &value), unpaired.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_paired_by_key_and_state() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut pairer = Pairer::new();
+        let mut unpaired = Vec::new();
+        let mut pair = |time, source, key_code, state, number| {
+            let now = ms(time);
+            unpaired.extend(pairer.expire(now).into_iter().map(|cell| cell.text));
+            pairer
+                .pair(now, source, key_code, state, number)
+                .map(|cell| cell.text)
+        };
+
+        assert_eq!(
+            pair(0, Source::Device, KeyCode::KeyA, ElementState::Pressed, 0),
+            None
+        );
+        assert_eq!(
+            pair(1, Source::Device, KeyCode::KeyB, ElementState::Pressed, 1),
+            None
+        );
+        assert_eq!(
+            pair(2, Source::Window, KeyCode::KeyA, ElementState::Pressed, 2).as_deref(),
+            Some("Device 0 +2.0ms")
+        );
+        assert_eq!(
+            pair(10, Source::Window, KeyCode::KeyA, ElementState::Released, 3),
+            None
+        );
+        assert_eq!(
+            pair(14, Source::Device, KeyCode::KeyA, ElementState::Released, 4).as_deref(),
+            Some("Window 3 +4.0ms")
+        );
+        // KeyB never gets its window event.
+        assert_eq!(
+            pair(
+                500,
+                Source::Device,
+                KeyCode::KeyB,
+                ElementState::Released,
+                5
+            ),
+            None
+        );
+        assert_eq!(unpaired, ["Device 1 unpaired"]);
+        assert_eq!(
+            pairer
+                .expire_all()
+                .into_iter()
+                .map(|cell| cell.text)
+                .collect::<Vec<_>>(),
+            ["Device 5 unpaired"]
+        );
+
+        let (summary, unpaired) = pairer.summary().unwrap();
+        assert_eq!(unpaired, 2);
+        assert_eq!(
+            summary.text,
+            "2 pairs, window latency -1.0ms on average (-4.0 to 2.0ms), \
+             2 unpaired: Device 1 KeyB Pressed, Device 5 KeyB Released"
+        );
+        assert!(pairer.summary().is_none());
+    }
+}
@@ -0,0 +1,499 @@
+//! Session recordings.
+//!
+//! A recording is a JSON Lines file. The first line is a [`Header`] and every
+//! following line is a [`Record`] holding one event.
+//!
+//! Version 2 added the [`Settings`] to the header. Recordings of version 1
+//! can still be read, and are replayed with the current settings.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Lines, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    event::{DeviceEvent, ElementState, Event, Ime, KeyEvent, WindowEvent},
+    keyboard::{Key, KeyCode, KeyLocation, ModifiersState, NativeKeyCode},
+};
+
+use crate::{
+    config::MouseBindings,
+    session::{RepeatMode, SplitPolicy},
+};
+
+pub const FORMAT_NAME: &str = "winit_keyboard_tester";
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Header {
+    pub format: String,
+    pub version: u32,
+    /// The OS the session was recorded on.
+    pub platform: String,
+    #[serde(default)]
+    pub settings: Option<Settings>,
+}
+
+/// The startup settings that decide how events are turned into tables, so
+/// that a replay produces the same tables as the live session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub manual_mode: bool,
+    pub split: SplitPolicy,
+    pub table_timeout: Duration,
+    pub repeats: RepeatMode,
+    pub ime_allowed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Record {
+    /// Microseconds since the recording started.
+    pub time_us: u64,
+    /// The modifiers that were in effect when the event arrived.
+    pub modifiers: ModifiersState,
+    pub event: RecordedEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum RecordedEvent {
+    Focused {
+        focused: bool,
+    },
+    KeyboardInput {
+        is_synthetic: bool,
+        event: KeyEventRecord,
+    },
+    DeviceKey {
+        physical_key: KeyCode,
+        state: ElementState,
+        #[serde(default)]
+        scan_code: Option<u32>,
+    },
+    ModifiersChanged {
+        modifiers: ModifiersState,
+    },
+    Ime {
+        ime: Ime,
+    },
+    /// The table control button (middle mouse by default) was pressed.
+    TableControl,
+    /// The dead key reset button (right mouse by default) was pressed.
+    DeadKeyReset,
+    /// The IME toggle button was pressed, which allows or disallows IME input.
+    ImeToggle,
+    /// The IME position button was pressed, which moves the IME to the
+    /// configured position.
+    ImePosition,
+    /// The repeat mode button was pressed, which switches to the next repeat
+    /// mode.
+    RepeatModeCycle,
+}
+
+impl RecordedEvent {
+    /// Converts the events the tester cares about. Everything else is `None`.
+    pub fn from_event<T>(event: &Event<'_, T>, mouse_bindings: &MouseBindings) -> Option<Self> {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::Focused(focused) => Some(Self::Focused { focused: *focused }),
+                WindowEvent::KeyboardInput {
+                    event,
+                    is_synthetic,
+                    ..
+                } => Some(Self::KeyboardInput {
+                    is_synthetic: *is_synthetic,
+                    event: KeyEventRecord::from_key_event(event),
+                }),
+                WindowEvent::ModifiersChanged(modifiers) => Some(Self::ModifiersChanged {
+                    modifiers: *modifiers,
+                }),
+                WindowEvent::Ime(ime) => Some(Self::Ime { ime: ime.clone() }),
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button,
+                    ..
+                } => mouse_bindings.action(*button),
+                _ => None,
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::Key(event),
+                ..
+            } => Some(Self::DeviceKey {
+                physical_key: event.physical_key,
+                state: event.state,
+                scan_code: native_scan_code(event.physical_key),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An owned copy of a [`KeyEvent`], including the results of the
+/// modifier supplement methods where the platform has them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyEventRecord {
+    pub physical_key: KeyCode,
+    pub logical_key: Key,
+    pub text: Option<String>,
+    pub location: KeyLocation,
+    pub state: ElementState,
+    pub repeat: bool,
+    #[serde(default)]
+    pub scan_code: Option<u32>,
+    #[serde(default)]
+    pub key_without_modifiers: Option<Key>,
+    #[serde(default)]
+    pub text_with_all_modifiers: Option<String>,
+}
+
+impl KeyEventRecord {
+    pub fn from_key_event(event: &KeyEvent) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+
+        Self {
+            physical_key: event.physical_key,
+            logical_key: event.logical_key.clone(),
+            text: event.text.as_ref().map(|text| text.to_string()),
+            location: event.location,
+            state: event.state,
+            repeat: event.repeat,
+            scan_code: native_scan_code(event.physical_key),
+            #[cfg(not(target_arch = "wasm32"))]
+            key_without_modifiers: Some(event.key_without_modifiers()),
+            #[cfg(target_arch = "wasm32")]
+            key_without_modifiers: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            text_with_all_modifiers: event.text_with_all_modifiers().map(str::to_string),
+            #[cfg(target_arch = "wasm32")]
+            text_with_all_modifiers: None,
+        }
+    }
+}
+
+/// Builders for the key events of tests.
+#[cfg(test)]
+impl KeyEventRecord {
+    /// An event of `physical_key` with the logical key `a` and no text.
+    pub fn for_test(physical_key: KeyCode, state: ElementState) -> Self {
+        Self {
+            physical_key,
+            logical_key: Key::Character("a".into()),
+            text: None,
+            location: KeyLocation::Standard,
+            state,
+            repeat: false,
+            scan_code: None,
+            key_without_modifiers: None,
+            text_with_all_modifiers: None,
+        }
+    }
+
+    pub fn with_repeat(self, repeat: bool) -> Self {
+        Self { repeat, ..self }
+    }
+
+    /// Uses `text` as both the logical key and the text.
+    pub fn with_text(self, text: &str) -> Self {
+        self.with_logical_key(Key::Character(text.into()), Some(text))
+    }
+
+    pub fn with_logical_key(self, logical_key: Key, text: Option<&str>) -> Self {
+        Self {
+            logical_key,
+            text: text.map(str::to_string),
+            ..self
+        }
+    }
+
+    pub fn into_event(self) -> RecordedEvent {
+        RecordedEvent::KeyboardInput {
+            is_synthetic: false,
+            event: self,
+        }
+    }
+}
+
+/// A window event of `physical_key` for tests, with the logical key `a` and
+/// no text.
+#[cfg(test)]
+pub fn key(physical_key: KeyCode, state: ElementState, repeat: bool) -> RecordedEvent {
+    KeyEventRecord::for_test(physical_key, state)
+        .with_repeat(repeat)
+        .into_event()
+}
+
+#[cfg(test)]
+pub fn device_key(physical_key: KeyCode, state: ElementState) -> RecordedEvent {
+    RecordedEvent::DeviceKey {
+        physical_key,
+        state,
+        scan_code: None,
+    }
+}
+
+/// The platform's native scan code for `key_code`, where it has one.
+///
+/// This has to be looked up while recording, since the mapping depends on the
+/// platform the event came from.
+pub fn native_scan_code(key_code: KeyCode) -> Option<u32> {
+    match key_code {
+        KeyCode::Unidentified(native_key_code) => match native_key_code {
+            NativeKeyCode::Windows(scan_code) => Some(scan_code.into()),
+            NativeKeyCode::MacOS(scan_code) => Some(scan_code.into()),
+            NativeKeyCode::Xkb(scan_code) | NativeKeyCode::Android(scan_code) => Some(scan_code),
+            NativeKeyCode::Unidentified => None,
+        },
+        _ => platform_scan_code(key_code),
+    }
+}
+
+/// Keeps the items of the first block where the condition holds, and those of
+/// the `else` block everywhere else.
+macro_rules! cfg_else {
+    (#[cfg($condition:meta)] { $($then:item)* } else { $($else:item)* }) => {
+        $(#[cfg($condition)] $then)*
+        $(#[cfg(not($condition))] $else)*
+    };
+}
+
+// The platforms where winit can turn key codes into scan codes.
+cfg_else! {
+    #[cfg(any(
+        target_os = "windows",
+        target_os = "macos",
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    {
+        fn platform_scan_code(key_code: KeyCode) -> Option<u32> {
+            use winit::platform::scancode::KeyCodeExtScancode;
+            key_code.to_scancode()
+        }
+    } else {
+        fn platform_scan_code(_: KeyCode) -> Option<u32> {
+            None
+        }
+    }
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, start: Instant, settings: &Settings) -> io::Result<Self> {
+        let mut recorder = Self {
+            out: BufWriter::new(File::create(path)?),
+            start,
+        };
+        let header = Header {
+            format: FORMAT_NAME.to_string(),
+            version: FORMAT_VERSION,
+            platform: std::env::consts::OS.to_string(),
+            settings: Some(settings.clone()),
+        };
+        recorder.write_line(&header)?;
+        Ok(recorder)
+    }
+
+    pub fn record(
+        &mut self,
+        now: Instant,
+        modifiers: ModifiersState,
+        event: RecordedEvent,
+    ) -> io::Result<()> {
+        let record = Record {
+            time_us: now.saturating_duration_since(self.start).as_micros() as u64,
+            modifiers,
+            event,
+        };
+        self.write_line(&record)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, value)?;
+        writeln!(self.out)?;
+        // Flush every line so that the recording survives a crash.
+        self.out.flush()
+    }
+}
+
+pub struct RecordReader {
+    header: Header,
+    lines: Lines<BufReader<File>>,
+}
+
+impl RecordReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(invalid_data("the recording is empty".to_string())),
+        };
+        if header.format != FORMAT_NAME {
+            return Err(invalid_data(format!("unknown format {:?}", header.format)));
+        }
+        if !(1..=FORMAT_VERSION).contains(&header.version) {
+            return Err(invalid_data(format!(
+                "unsupported format version {} (expected 1 to {})",
+                header.version, FORMAT_VERSION
+            )));
+        }
+
+        Ok(Self { header, lines })
+    }
+
+    /// The settings of the recorded session, if the recording has them.
+    pub fn settings(&self) -> Option<&Settings> {
+        self.header.settings.as_ref()
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).map_err(io::Error::from));
+        }
+    }
+}
+
+impl Record {
+    /// The point in time the event arrived at, relative to `start`.
+    pub fn instant(&self, start: Instant) -> Instant {
+        start + Duration::from_micros(self.time_us)
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "winit_keyboard_tester_{}_{}.jsonl",
+            std::process::id(),
+            name
+        ))
+    }
+
+    /// Opens a recording with the given lines and reads all of its records.
+    fn read(name: &str, lines: &[&str]) -> io::Result<Vec<Record>> {
+        let path = temp_path(name);
+        fs::write(&path, lines.join("\n")).unwrap();
+        let records = RecordReader::open(&path).and_then(|reader| reader.collect());
+        fs::remove_file(&path).unwrap();
+        records
+    }
+
+    fn header(format: &str, version: u32) -> String {
+        serde_json::json!({ "format": format, "version": version, "platform": "linux" }).to_string()
+    }
+
+    #[test]
+    fn native_key_codes_keep_their_scan_code() {
+        let unidentified = |native| native_scan_code(KeyCode::Unidentified(native));
+
+        assert_eq!(unidentified(NativeKeyCode::Windows(0xe05b)), Some(0xe05b));
+        assert_eq!(unidentified(NativeKeyCode::MacOS(0x3f)), Some(0x3f));
+        assert_eq!(unidentified(NativeKeyCode::Xkb(0x1a2)), Some(0x1a2));
+        assert_eq!(unidentified(NativeKeyCode::Android(0x11c)), Some(0x11c));
+        assert_eq!(unidentified(NativeKeyCode::Unidentified), None);
+    }
+
+    #[test]
+    fn identified_key_codes_use_the_platform_scan_code() {
+        // Windows and evdev, which winit uses on Linux and the BSDs, number the
+        // keys of a PC keyboard the same way.
+        let expected = match std::env::consts::OS {
+            "macos" => Some(0x00),
+            "windows" | "linux" | "dragonfly" | "freebsd" | "netbsd" | "openbsd" => Some(0x1e),
+            _ => None,
+        };
+        assert_eq!(native_scan_code(KeyCode::KeyA), expected);
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        let header = header(FORMAT_NAME, FORMAT_VERSION);
+        let record = |time_us, event| {
+            serde_json::to_string(&Record {
+                time_us,
+                modifiers: ModifiersState::empty(),
+                event,
+            })
+            .unwrap()
+        };
+        let records = read(
+            "blank_lines",
+            &[
+                &header,
+                "",
+                &record(5, RecordedEvent::TableControl),
+                "  ",
+                &record(9, RecordedEvent::DeadKeyReset),
+                "",
+            ],
+        )
+        .unwrap();
+        let times: Vec<_> = records.iter().map(|record| record.time_us).collect();
+        assert_eq!(times, [5, 9]);
+    }
+
+    #[test]
+    fn version_1_recordings_have_no_settings() {
+        let path = temp_path("version_1");
+        fs::write(&path, header(FORMAT_NAME, 1)).unwrap();
+        let reader = RecordReader::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reader.unwrap().settings(), None);
+    }
+
+    #[test]
+    fn invalid_recordings_are_rejected() {
+        let error = |name, lines: &[&str]| read(name, lines).unwrap_err().to_string();
+
+        assert_eq!(error("empty", &[]), "the recording is empty");
+        assert_eq!(
+            error("wrong_format", &[&header("something_else", FORMAT_VERSION)]),
+            "unknown format \"something_else\""
+        );
+        assert_eq!(
+            error("wrong_version", &[&header(FORMAT_NAME, FORMAT_VERSION + 1)]),
+            format!(
+                "unsupported format version {} (expected 1 to {})",
+                FORMAT_VERSION + 1,
+                FORMAT_VERSION
+            )
+        );
+        assert!(read("version_0", &[&header(FORMAT_NAME, 0)]).is_err());
+        assert!(read("bad_header", &["not json"]).is_err());
+        assert!(read(
+            "bad_record",
+            &[&header(FORMAT_NAME, FORMAT_VERSION), r#"{"time_us":1}"#]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! Headless replay of recorded sessions.
+
+use std::{io, time::Instant};
+
+use crate::{record::RecordReader, session::Session, TablePrinter};
+
+/// Feeds every event in the recording through `session` and prints the
+/// resulting tables.
+///
+/// The table timeout is evaluated against the recorded timestamps, so the
+/// tables are split exactly as they were during the live session, as long as
+/// `session` has the [`Settings`](crate::record::Settings) of the recording.
+pub fn replay<P: TablePrinter + ?Sized>(
+    reader: RecordReader,
+    mut session: Session,
+    table_printer: &mut P,
+) -> io::Result<()> {
+    let start = Instant::now();
+
+    table_printer.begin_new_table(session.table());
+
+    for record in reader {
+        let record = record?;
+        let now = record.instant(start);
+
+        // The live event loop polls in between events, so a table that timed
+        // out before this event arrived has already been finished.
+        let mut output = session.tick(now);
+        output.extend(session.handle_event(now, &record.event));
+        output.extend(session.tick(now));
+
+        for output in output {
+            output.print(session.table(), table_printer);
+        }
+    }
+
+    for output in session.finish() {
+        output.print(session.table(), table_printer);
+    }
+    table_printer.finish();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, time::Duration};
+
+    use winit::{
+        event::ElementState,
+        keyboard::{KeyCode, ModifiersState},
+    };
+
+    use super::*;
+    use crate::{
+        build_table, column,
+        record::{RecordedEvent, Recorder, Settings},
+        session::{RepeatMode, SplitPolicy},
+        MarkdownTablePrinter,
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "winit_keyboard_tester_{}_{}.jsonl",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn recordings_are_replayed_into_tables() {
+        let path = temp_path("replay");
+        let start = Instant::now();
+        let settings = Settings {
+            manual_mode: false,
+            split: SplitPolicy::Events(5),
+            // Shorter than the default, so the tables are only split if it's
+            // read back.
+            table_timeout: Duration::from_secs(1),
+            repeats: RepeatMode::Raw,
+            ime_allowed: false,
+        };
+        let mut recorder = Recorder::create(&path, start, &settings).unwrap();
+        let second_table = Duration::from_millis(100) + settings.table_timeout * 2;
+        for (time, key_code, state) in [
+            (
+                Duration::from_millis(0),
+                KeyCode::KeyA,
+                ElementState::Pressed,
+            ),
+            (
+                Duration::from_millis(100),
+                KeyCode::KeyA,
+                ElementState::Released,
+            ),
+            (second_table, KeyCode::KeyB, ElementState::Pressed),
+        ]
+        .iter()
+        {
+            let event = RecordedEvent::DeviceKey {
+                physical_key: *key_code,
+                state: *state,
+                scan_code: None,
+            };
+            recorder
+                .record(start + *time, ModifiersState::empty(), event)
+                .unwrap();
+        }
+        drop(recorder);
+
+        let reader = RecordReader::open(&path).unwrap();
+        assert_eq!(reader.settings(), Some(&settings));
+
+        let mut table = build_table();
+        table.select_columns(&[column::KIND, column::STATE, column::KEY_CODE]);
+        let mut session = Session::new(table, start);
+        session.set_table_timeout(reader.settings().unwrap().table_timeout);
+        let mut buffer = Vec::new();
+        {
+            let mut table_printer = MarkdownTablePrinter::new(&mut buffer);
+            replay(reader, session, &mut table_printer).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+
+        let output = String::from_utf8(buffer).unwrap();
+        let rows: Vec<_> = output
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|line| line.starts_with("| Device"))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "| Device | Pressed | KeyA |",
+                "| Device | Released | KeyA |",
+                "| Device | Pressed | KeyB |",
+            ]
+        );
+        assert_eq!(output.matches("| Kind").count(), 2, "{}", output);
+    }
+}
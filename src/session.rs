@@ -0,0 +1,1328 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use winit::{
+    event::{ElementState, Ime},
+    keyboard::{KeyCode, ModifiersState},
+};
+
+use crate::{
+    anomaly::AnomalyDetector,
+    column,
+    dead_keys::DeadKeyTracker,
+    expect::Expectations,
+    ime::preedit_cell,
+    key_code_to_string, key_to_string, key_without_modifiers,
+    modifiers::{ModifierChecker, ModifierKeys},
+    nice_text,
+    pairing::{Pairer, Source},
+    record::RecordedEvent,
+    scan_code_to_string, text_with_all_modifiers,
+    timing::{describe_repeats, KeyTimings},
+    Cell, Row, Table, TablePrinter, TABLE_TIMEOUT,
+};
+
+/// The bookkeeping that turns a stream of events into table rows.
+///
+/// A session doesn't read the clock itself. Every method that depends on time
+/// takes the current [`Instant`] instead, so it can be driven by the event
+/// loop, a recording or a test alike.
+pub struct Session {
+    table: Table,
+    raw_keys_pressed: HashMap<KeyCode, i32>,
+    repeated_keys: HashMap<KeyCode, i32>,
+    /// When each held key was pressed and repeated, in the detailed repeat
+    /// mode.
+    repeat_times: HashMap<KeyCode, Vec<Instant>>,
+    focused: bool,
+    event_number: u16,
+    pressed_count: i32,
+    modifiers: ModifiersState,
+    modifier_keys: ModifierKeys,
+    /// Whether key rows show the modifiers too, and the modifiers of the
+    /// previous key row in the current table.
+    key_modifiers: bool,
+    last_key_modifiers: ModifiersState,
+    manual_mode: bool,
+    ime_allowed: bool,
+    split_policy: SplitPolicy,
+    repeat_mode: RepeatMode,
+    /// Whether the repeats column was turned on by cycling the repeat mode,
+    /// so that it's turned off again when the detailed mode is left.
+    repeats_column_cycled: bool,
+    table_timeout: Duration,
+    last_change: Instant,
+    /// When the first and the latest row of the current table arrived.
+    table_start: Option<Instant>,
+    last_row: Option<Instant>,
+    skip_timeout: bool,
+    expectations: Option<Expectations>,
+    anomaly_detector: Option<AnomalyDetector>,
+    pairer: Option<Pairer>,
+    modifier_checker: Option<ModifierChecker>,
+    dead_key_tracker: Option<DeadKeyTracker>,
+    key_timings: Option<KeyTimings>,
+    /// The text committed by the IME in the current table.
+    composed: String,
+}
+
+#[derive(Debug)]
+pub enum Output {
+    /// A new row.
+    Row(Row),
+    /// Replaces the previous row if that was an update too. Otherwise, this is
+    /// a new row.
+    UpdateRow(Row),
+    /// The current table is finished and a new one begins.
+    NewTable,
+}
+
+impl Output {
+    pub fn print<P: TablePrinter + ?Sized>(&self, table: &Table, table_printer: &mut P) {
+        match self {
+            Output::Row(row) => table_printer.print_row(table, row),
+            Output::UpdateRow(row) => table_printer.update_row(table, row),
+            Output::NewTable => table_printer.begin_new_table(table),
+        }
+    }
+}
+
+/// When a table is finished automatically, outside of manual mode.
+///
+/// The table control button finishes the current table regardless of the
+/// policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SplitPolicy {
+    /// Finish the table once every key has been released and no events
+    /// arrived for the table timeout.
+    #[default]
+    Timeout,
+    /// Begin a new table when an event arrives after a gap of at least the
+    /// table timeout, even if keys are still being held.
+    IdleGap,
+    /// Finish the table when the window gains or loses focus.
+    Focus,
+    /// Finish the table after this many rows.
+    Events(u16),
+    /// Only finish tables with the table control button.
+    Never,
+}
+
+impl FromStr for SplitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), arg) {
+            ("timeout", None) => Ok(Self::Timeout),
+            ("idle", None) => Ok(Self::IdleGap),
+            ("focus", None) => Ok(Self::Focus),
+            ("events", Some(count)) => match count.parse() {
+                Ok(0) | Err(_) => Err(format!("invalid event count {:?}", count)),
+                Ok(count) => Ok(Self::Events(count)),
+            },
+            ("never", None) => Ok(Self::Never),
+            _ => Err("expected one of: timeout, idle, focus, events:<N>, never".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for SplitPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timeout"),
+            Self::IdleGap => write!(f, "idle"),
+            Self::Focus => write!(f, "focus"),
+            Self::Events(count) => write!(f, "events:{}", count),
+            Self::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl Serialize for SplitPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SplitPolicy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// How repeated key events are shown.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    /// Count the repeats of a key in a single row
+    #[default]
+    Collapsed,
+    /// Count the repeats in a single row, and describe their timing in the
+    /// `Repeats` column of the release
+    Detailed,
+    /// Print every repeat as its own row
+    Raw,
+}
+
+pub enum TableTimeout {
+    /// The current table is either empty or keys are still being held.
+    Inactive,
+    /// The current table will be finished after the given duration.
+    Pending(Duration),
+    /// The current table should be finished now.
+    Expired,
+}
+
+impl Session {
+    pub fn new(table: Table, now: Instant) -> Self {
+        Self {
+            table,
+            raw_keys_pressed: HashMap::new(),
+            repeated_keys: HashMap::new(),
+            repeat_times: HashMap::new(),
+            focused: true,
+            event_number: 0,
+            pressed_count: 0,
+            modifiers: Default::default(),
+            modifier_keys: ModifierKeys::default(),
+            key_modifiers: false,
+            last_key_modifiers: ModifiersState::empty(),
+            manual_mode: false,
+            ime_allowed: false,
+            split_policy: SplitPolicy::default(),
+            repeat_mode: RepeatMode::default(),
+            repeats_column_cycled: false,
+            table_timeout: TABLE_TIMEOUT,
+            last_change: now,
+            table_start: None,
+            last_row: None,
+            skip_timeout: false,
+            expectations: None,
+            anomaly_detector: None,
+            pairer: None,
+            modifier_checker: None,
+            dead_key_tracker: None,
+            key_timings: None,
+            composed: String::new(),
+        }
+    }
+
+    pub fn manual_mode(&self) -> bool {
+        self.manual_mode
+    }
+
+    pub fn set_manual_mode(&mut self, manual_mode: bool) {
+        self.manual_mode = manual_mode;
+    }
+
+    /// Whether IME input is allowed, which the IME toggle button switches.
+    pub fn ime_allowed(&self) -> bool {
+        self.ime_allowed
+    }
+
+    pub fn set_ime_allowed(&mut self, ime_allowed: bool) {
+        self.ime_allowed = ime_allowed;
+    }
+
+    /// Shows the modifiers on every window and device row from now on.
+    pub fn set_key_modifiers(&mut self, key_modifiers: bool) {
+        self.key_modifiers = key_modifiers;
+    }
+
+    pub fn set_split_policy(&mut self, split_policy: SplitPolicy) {
+        self.split_policy = split_policy;
+    }
+
+    pub fn set_repeat_mode(&mut self, repeat_mode: RepeatMode) {
+        self.repeat_mode = repeat_mode;
+    }
+
+    pub fn set_table_timeout(&mut self, table_timeout: Duration) {
+        self.table_timeout = table_timeout;
+    }
+
+    /// Checks key presses against a reference from now on.
+    pub fn set_expectations(&mut self, expectations: Expectations) {
+        self.expectations = Some(expectations);
+    }
+
+    /// Flags events that don't add up from now on.
+    pub fn set_anomaly_detector(&mut self, anomaly_detector: AnomalyDetector) {
+        self.anomaly_detector = Some(anomaly_detector);
+    }
+
+    /// Pairs device events with window events from now on.
+    pub fn set_pairer(&mut self, pairer: Pairer) {
+        self.pairer = Some(pairer);
+    }
+
+    /// Checks the reported modifiers against the held keys from now on.
+    pub fn set_modifier_checker(&mut self, modifier_checker: ModifierChecker) {
+        self.modifier_checker = Some(modifier_checker);
+    }
+
+    /// Groups dead keys with the keys that follow them from now on.
+    pub fn set_dead_key_tracker(&mut self, dead_key_tracker: DeadKeyTracker) {
+        self.dead_key_tracker = Some(dead_key_tracker);
+    }
+
+    /// Measures how long keys are held and how they repeat from now on.
+    pub fn set_key_timings(&mut self, key_timings: KeyTimings) {
+        self.key_timings = Some(key_timings);
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    pub fn handle_event(&mut self, now: Instant, event: &RecordedEvent) -> Vec<Output> {
+        let mut output = Vec::new();
+
+        if let Some(unpaired) = self.pairer.as_mut().map(|pairer| pairer.expire(now)) {
+            self.push_unpaired(unpaired, &mut output);
+        }
+
+        // The table control button decides for itself whether to begin a new
+        // table, so an idle gap mustn't empty the table before it.
+        if self.split_policy == SplitPolicy::IdleGap
+            && !matches!(event, RecordedEvent::TableControl)
+            && self.splits_automatically()
+            && self.last_change + self.table_timeout <= now
+        {
+            self.begin_new_table(&mut output);
+        }
+
+        let mismatch = self
+            .expectations
+            .as_mut()
+            .and_then(|expectations| expectations.handle_event(event));
+        let anomaly = self
+            .anomaly_detector
+            .as_mut()
+            .and_then(|anomaly_detector| anomaly_detector.handle_event(event));
+        let dead_key = self
+            .dead_key_tracker
+            .as_mut()
+            .and_then(|dead_key_tracker| dead_key_tracker.handle_event(event));
+        if let Some(key_timings) = self.key_timings.as_mut() {
+            key_timings.handle_event(now, event);
+        }
+        self.modifier_keys.handle_event(event);
+        let modifier_keys = &self.modifier_keys;
+        let modifiers = self.modifiers;
+        let modifier_check = self.modifier_checker.as_mut().and_then(|modifier_checker| {
+            modifier_checker.handle_event(event, modifier_keys, modifiers)
+        });
+
+        match event {
+            RecordedEvent::Focused { focused: focus } => {
+                if self.event_number > 0 {
+                    let (time, delta) = self.stamp(now);
+                    let row = self
+                        .table
+                        .print_table_line()
+                        .column(column::NUMBER, self.event_number)
+                        .column(column::TIME, time)
+                        .column(column::DELTA, delta)
+                        .column(column::KIND, "Focus")
+                        .column(column::STATE, if *focus { "Received" } else { "Lost" });
+                    let row = match anomaly {
+                        Some(anomaly) => row.column(column::ANOMALY, anomaly).highlight(true),
+                        None => row,
+                    };
+                    output.push(Output::Row(row.finish()));
+                    self.event_number += 1;
+                }
+                self.focused = *focus;
+
+                if self.split_policy == SplitPolicy::Focus && self.splits_automatically() {
+                    self.begin_new_table(&mut output);
+                }
+            }
+            RecordedEvent::KeyboardInput {
+                event,
+                is_synthetic,
+            } => {
+                let (time, delta) = self.stamp(now);
+                let modifiers = self.key_modifiers_cell();
+                let row = self
+                    .table
+                    .print_table_line()
+                    .column(column::NUMBER, self.event_number)
+                    .column(column::TIME, time)
+                    .column(column::DELTA, delta)
+                    .column(column::KIND, "Window")
+                    .column(column::SYNTH, *is_synthetic)
+                    .column_with(column::KEY_CODE, || {
+                        Cell::typed(key_code_to_string(&event.physical_key), &event.physical_key)
+                    })
+                    .column_with(column::KEY, || {
+                        Cell::typed(key_to_string(&event.logical_key), &event.logical_key)
+                    })
+                    .column_with(column::LOCATION, || {
+                        Cell::typed(format!("{:?}", event.location), &event.location)
+                    })
+                    .column_with(column::TEXT, || {
+                        let text = event
+                            .text
+                            .as_ref()
+                            .map(nice_text)
+                            .unwrap_or_else(|| "".to_string());
+                        Cell::typed(text, &event.text)
+                    })
+                    .column_with(column::KEY_NO_MOD, || {
+                        Cell::typed(key_without_modifiers(event), &event.key_without_modifiers)
+                    })
+                    .column_with(column::TEXT_ALL_MODS, || {
+                        Cell::typed(
+                            text_with_all_modifiers(event),
+                            &event.text_with_all_modifiers,
+                        )
+                    })
+                    .column_with(column::SCAN_CODE, || {
+                        Cell::typed(scan_code_to_string(event.scan_code), &event.scan_code)
+                    });
+                let row = match modifiers {
+                    Some(modifiers) => row.column(column::MODIFIERS, modifiers),
+                    None => row,
+                };
+                let row = match mismatch {
+                    Some(mismatch) => row.column(column::MISMATCH, mismatch).highlight(true),
+                    None => row,
+                };
+                let row = match anomaly {
+                    Some(anomaly) => row.column(column::ANOMALY, anomaly).highlight(true),
+                    None => row,
+                };
+                let row = match modifier_check {
+                    Some(check) => row.column(column::MOD_CHECK, check).highlight(true),
+                    None => row,
+                };
+                let row = match dead_key {
+                    Some((sequence, problem)) => {
+                        row.column(column::DEAD_KEYS, sequence).highlight(problem)
+                    }
+                    None => row,
+                };
+
+                if !event.repeat {
+                    let row = row.column_with(column::STATE, || {
+                        Cell::typed(format!("{:?}", event.state), &event.state)
+                    });
+                    let number = self.event_number;
+                    let pair = self.pairer.as_mut().and_then(|pairer| {
+                        pairer.pair(now, Source::Window, event.physical_key, event.state, number)
+                    });
+                    let row = match pair {
+                        Some(pair) => row.column(column::PAIR, pair),
+                        None => row,
+                    };
+                    let repeats = match event.state {
+                        ElementState::Pressed => {
+                            if self.repeat_mode == RepeatMode::Detailed {
+                                self.repeat_times.insert(event.physical_key, vec![now]);
+                            }
+                            None
+                        }
+                        ElementState::Released => {
+                            match self.repeat_times.remove(&event.physical_key) {
+                                Some(times) if self.repeat_mode == RepeatMode::Detailed => {
+                                    describe_repeats(&times)
+                                }
+                                _ => None,
+                            }
+                        }
+                    };
+                    let row = match repeats {
+                        Some(repeats) => row.column(column::REPEATS, repeats),
+                        None => row,
+                    };
+                    output.push(Output::Row(row.finish()));
+
+                    self.event_number += 1;
+
+                    match event.state {
+                        ElementState::Pressed => self.pressed_count += 1,
+                        ElementState::Released => {
+                            self.repeated_keys.remove(&event.physical_key);
+                            // The press may have been forgotten by the table
+                            // control button.
+                            self.pressed_count = (self.pressed_count - 1).max(0);
+                        }
+                    }
+                } else {
+                    if self.repeat_mode == RepeatMode::Detailed {
+                        if let Some(times) = self.repeat_times.get_mut(&event.physical_key) {
+                            times.push(now);
+                        }
+                    }
+                    let repeat_count = self.repeated_keys.entry(event.physical_key).or_insert(1);
+                    let row = row.column_with(column::STATE, || repeat_cell(*repeat_count));
+                    if self.repeat_mode == RepeatMode::Raw {
+                        output.push(Output::Row(row.finish()));
+                        self.event_number += 1;
+                    } else {
+                        if *repeat_count == 1 {
+                            self.event_number += 1;
+                        }
+                        output.push(Output::UpdateRow(row.finish()));
+                    }
+                    *repeat_count += 1;
+                }
+            }
+            RecordedEvent::DeviceKey {
+                physical_key,
+                state,
+                scan_code,
+            } => {
+                if self.focused || self.pressed_count > 0 {
+                    let (time, delta) = self.stamp(now);
+                    let modifiers = self.key_modifiers_cell();
+                    let pressed_count = &mut self.pressed_count;
+                    let repeat_count = match state {
+                        ElementState::Pressed => Some(
+                            self.raw_keys_pressed
+                                .entry(*physical_key)
+                                .or_insert_with(|| {
+                                    *pressed_count += 1;
+                                    0
+                                }),
+                        ),
+                        ElementState::Released => {
+                            if self.raw_keys_pressed.remove(physical_key).is_some() {
+                                *pressed_count -= 1;
+                            }
+                            None
+                        }
+                    };
+
+                    let row = self
+                        .table
+                        .print_table_line()
+                        .column(column::NUMBER, self.event_number)
+                        .column(column::TIME, time)
+                        .column(column::DELTA, delta)
+                        .column(column::KIND, "Device")
+                        .column_with(column::KEY_CODE, || {
+                            Cell::typed(key_code_to_string(physical_key), physical_key)
+                        })
+                        .column_with(column::SCAN_CODE, || {
+                            Cell::typed(scan_code_to_string(*scan_code), scan_code)
+                        });
+                    let row = match modifiers {
+                        Some(modifiers) => row.column(column::MODIFIERS, modifiers),
+                        None => row,
+                    };
+                    let row = match anomaly {
+                        Some(anomaly) => row.column(column::ANOMALY, anomaly).highlight(true),
+                        None => row,
+                    };
+
+                    match repeat_count {
+                        Some(repeat_count) if *repeat_count > 0 => {
+                            let row = row.column_with(column::STATE, || repeat_cell(*repeat_count));
+                            output.push(Output::UpdateRow(row.finish()));
+                        }
+                        _ => {
+                            let row = row.column_with(column::STATE, || {
+                                Cell::typed(format!("{:?}", state), state)
+                            });
+                            let number = self.event_number;
+                            let pair = self.pairer.as_mut().and_then(|pairer| {
+                                pairer.pair(now, Source::Device, *physical_key, *state, number)
+                            });
+                            let row = match pair {
+                                Some(pair) => row.column(column::PAIR, pair),
+                                None => row,
+                            };
+                            output.push(Output::Row(row.finish()));
+                            self.event_number += 1;
+                        }
+                    }
+                }
+            }
+            RecordedEvent::ModifiersChanged { modifiers } => {
+                self.modifiers = *modifiers;
+                if !self.modifiers.is_empty() || self.event_number != 0 {
+                    let (time, delta) = self.stamp(now);
+                    let row = self
+                        .table
+                        .print_table_line()
+                        .column(column::NUMBER, self.event_number)
+                        .column(column::TIME, time)
+                        .column(column::DELTA, delta)
+                        .column(column::KIND, "ModC")
+                        .column_with(column::MODIFIERS, || {
+                            self.modifier_keys.cell(self.modifiers)
+                        });
+                    let row = match modifier_check {
+                        Some(check) => row.column(column::MOD_CHECK, check).highlight(true),
+                        None => row,
+                    };
+                    output.push(Output::Row(row.finish()));
+
+                    self.event_number += 1;
+                }
+            }
+            RecordedEvent::Ime { ime } => {
+                if let Ime::Commit(text) = ime {
+                    self.composed.push_str(text);
+                }
+                let (time, delta) = self.stamp(now);
+                let mut row = self
+                    .table
+                    .print_table_line()
+                    .column(column::NUMBER, self.event_number)
+                    .column(column::TIME, time)
+                    .column(column::DELTA, delta)
+                    .column(column::KIND, "IME")
+                    .column(
+                        column::STATE,
+                        match ime {
+                            Ime::Enabled => "Enabled",
+                            Ime::Preedit(_, _) => "Preedit",
+                            Ime::Commit(_) => "Commit",
+                            Ime::Disabled => "Disabled",
+                        },
+                    );
+                match ime {
+                    Ime::Preedit(text, cursor) => {
+                        row = row
+                            .column_with(column::TEXT, || Cell::typed(format!("{:?}", text), text))
+                            .column_with(column::PREEDIT, || preedit_cell(text, *cursor));
+                    }
+                    Ime::Commit(text) => {
+                        row = row
+                            .column_with(column::TEXT, || Cell::typed(format!("{:?}", text), text))
+                            .column_with(column::COMMIT, || Cell::typed(nice_text(text), text));
+                    }
+                    Ime::Enabled | Ime::Disabled => {}
+                }
+                let row = row.column_with(column::COMPOSED, || {
+                    Cell::typed(nice_text(&self.composed), &self.composed)
+                });
+                output.push(Output::Row(row.finish()));
+
+                self.event_number += 1;
+            }
+            RecordedEvent::TableControl => {
+                if self.manual_mode {
+                    if self.event_number == 0 {
+                        self.manual_mode = false;
+                    } else {
+                        self.begin_new_table(&mut output);
+                        self.forget_held_keys();
+                    }
+                } else {
+                    if self.event_number == 0 {
+                        self.manual_mode = true;
+                    } else {
+                        self.skip_timeout = true;
+                        self.forget_held_keys();
+                    }
+                }
+            }
+            RecordedEvent::ImeToggle | RecordedEvent::ImePosition => {
+                if let RecordedEvent::ImeToggle = event {
+                    self.ime_allowed = !self.ime_allowed;
+                }
+                let state = match (event, self.ime_allowed) {
+                    (RecordedEvent::ImePosition, _) => "Position",
+                    (_, true) => "Allow",
+                    (_, false) => "Disallow",
+                };
+                let (time, delta) = self.stamp(now);
+                let row = self
+                    .table
+                    .print_table_line()
+                    .column(column::NUMBER, self.event_number)
+                    .column(column::TIME, time)
+                    .column(column::DELTA, delta)
+                    .column(column::KIND, "IME")
+                    .column(column::STATE, state)
+                    .finish();
+                output.push(Output::Row(row));
+                self.event_number += 1;
+            }
+            RecordedEvent::RepeatModeCycle => {
+                self.repeat_mode = match self.repeat_mode {
+                    RepeatMode::Collapsed => RepeatMode::Detailed,
+                    RepeatMode::Detailed => RepeatMode::Raw,
+                    RepeatMode::Raw => RepeatMode::Collapsed,
+                };
+                // Keys pressed in another mode have no repeat times to show.
+                self.repeat_times.clear();
+                // The column only shows up in the header of a new table, and
+                // only stays there while repeats are detailed, unless the
+                // options turned it on.
+                let detailed = self.repeat_mode == RepeatMode::Detailed;
+                let mut column_changed = false;
+                if let Some(column) = self.table.column_mut(column::REPEATS) {
+                    if detailed && !column.enabled {
+                        column.enabled = true;
+                        self.repeats_column_cycled = true;
+                        column_changed = true;
+                    } else if !detailed && self.repeats_column_cycled {
+                        column.enabled = false;
+                        self.repeats_column_cycled = false;
+                        column_changed = true;
+                    }
+                }
+                if column_changed {
+                    if self.event_number == 0 {
+                        output.push(Output::NewTable);
+                    } else {
+                        self.begin_new_table(&mut output);
+                    }
+                }
+                let (time, delta) = self.stamp(now);
+                let row = self
+                    .table
+                    .print_table_line()
+                    .column(column::NUMBER, self.event_number)
+                    .column(column::TIME, time)
+                    .column(column::DELTA, delta)
+                    .column(column::KIND, "Repeats")
+                    .column(column::STATE, format!("{:?}", self.repeat_mode))
+                    .finish();
+                output.push(Output::Row(row));
+                self.event_number += 1;
+            }
+            RecordedEvent::DeadKeyReset => {
+                let (time, delta) = self.stamp(now);
+                let row = self
+                    .table
+                    .print_table_line()
+                    .column(column::NUMBER, self.event_number)
+                    .column(column::TIME, time)
+                    .column(column::DELTA, delta)
+                    .column(column::KIND, "DeadRST");
+                let row = match dead_key {
+                    Some((sequence, _)) => row.column(column::DEAD_KEYS, sequence),
+                    None => row,
+                };
+                output.push(Output::Row(row.finish()));
+                self.event_number += 1;
+            }
+        }
+
+        if let SplitPolicy::Events(count) = self.split_policy {
+            if self.event_number >= count && self.splits_automatically() {
+                self.begin_new_table(&mut output);
+            }
+        }
+
+        self.last_change = now;
+
+        output
+    }
+
+    /// Stops waiting for the keys that are held now, like the held keys of a
+    /// chord. Their releases are still shown, but don't count.
+    fn forget_held_keys(&mut self) {
+        self.pressed_count = 0;
+        self.raw_keys_pressed.clear();
+        self.repeated_keys.clear();
+        self.repeat_times.clear();
+        self.modifiers = Default::default();
+    }
+
+    /// Finishes the current table if it has timed out.
+    pub fn tick(&mut self, now: Instant) -> Vec<Output> {
+        let mut output = Vec::new();
+        if let Some(unpaired) = self.pairer.as_mut().map(|pairer| pairer.expire(now)) {
+            self.push_unpaired(unpaired, &mut output);
+        }
+        if let TableTimeout::Expired = self.table_timeout(now) {
+            self.begin_new_table(&mut output);
+        }
+        output
+    }
+
+    /// Finishes the session, once no more events will arrive.
+    pub fn finish(&mut self) -> Vec<Output> {
+        let mut output = Vec::new();
+        if self.event_number > 0 {
+            self.push_summary(&mut output);
+        }
+        output
+    }
+
+    pub fn table_timeout(&self, now: Instant) -> TableTimeout {
+        if !self.splits_automatically() {
+            TableTimeout::Inactive
+        } else if self.skip_timeout {
+            TableTimeout::Expired
+        } else if self.split_policy != SplitPolicy::Timeout
+            || self.pressed_count != 0
+            || !self.modifiers.is_empty()
+        {
+            TableTimeout::Inactive
+        } else if self.last_change + self.table_timeout <= now {
+            TableTimeout::Expired
+        } else {
+            TableTimeout::Pending(self.table_timeout - now.duration_since(self.last_change))
+        }
+    }
+
+    /// Whether there's a table that may be finished without the table control
+    /// button.
+    fn splits_automatically(&self) -> bool {
+        !self.manual_mode && self.event_number > 0
+    }
+
+    fn begin_new_table(&mut self, output: &mut Vec<Output>) {
+        self.push_summary(output);
+        output.push(Output::NewTable);
+        self.event_number = 0;
+        self.table_start = None;
+        self.last_row = None;
+        self.last_key_modifiers = ModifiersState::empty();
+        self.composed.clear();
+        self.skip_timeout = false;
+    }
+
+    /// The time of a row that arrived at `now`, relative to the beginning of
+    /// the table, and the time since the previous row.
+    fn stamp(&mut self, now: Instant) -> (Cell, Cell) {
+        let table_start = *self.table_start.get_or_insert(now);
+        let last_row = self.last_row.replace(now).unwrap_or(now);
+        let time = now.duration_since(table_start).as_secs_f64() * 1000.0;
+        let delta = now.duration_since(last_row).as_secs_f64() * 1000.0;
+        (
+            Cell::typed(format!("{:.1}ms", time), &time),
+            Cell::typed(format!("+{:.1}ms", delta), &delta),
+        )
+    }
+
+    /// The modifiers of a key row if key rows show them, prefixed with `*` if
+    /// they changed since the previous key row of the table. The first key
+    /// row of a table is compared to no modifiers.
+    fn key_modifiers_cell(&mut self) -> Option<Cell> {
+        if !self.key_modifiers {
+            return None;
+        }
+        let cell = self.modifier_keys.cell(self.modifiers);
+        let changed = self.modifiers != self.last_key_modifiers;
+        self.last_key_modifiers = self.modifiers;
+        if changed {
+            Some(Cell::typed(format!("*{}", cell.text), &cell.value))
+        } else {
+            Some(cell)
+        }
+    }
+
+    /// Sums up how the presses in the current table compared to the
+    /// reference, the anomalies in it, how its events paired up, whether the
+    /// modifiers added up, its dead key sequences and the timings of its keys.
+    /// Marks events that didn't get a pair, each on a row of its own.
+    fn push_unpaired(&self, unpaired: Vec<Cell>, output: &mut Vec<Output>) {
+        for cell in unpaired {
+            let row = self
+                .table
+                .print_table_line()
+                .column(column::KIND, "Pair")
+                .column(column::PAIR, cell)
+                .highlight(true)
+                .finish();
+            output.push(Output::Row(row));
+        }
+    }
+
+    fn push_summary(&mut self, output: &mut Vec<Output>) {
+        if let Some((summary, mismatch_count)) =
+            self.expectations.as_mut().and_then(Expectations::summary)
+        {
+            let row = self
+                .table
+                .print_table_line()
+                .column(column::KIND, "Check")
+                .column(column::MISMATCH, summary)
+                .highlight(mismatch_count > 0)
+                .finish();
+            output.push(Output::Row(row));
+        }
+        if let Some(anomaly_detector) = self.anomaly_detector.as_mut() {
+            let (summary, anomaly_count) = anomaly_detector.summary();
+            let row = self
+                .table
+                .print_table_line()
+                .column(column::KIND, "Anom")
+                .column(column::ANOMALY, summary)
+                .highlight(anomaly_count > 0)
+                .finish();
+            output.push(Output::Row(row));
+        }
+        if let Some(unpaired) = self.pairer.as_mut().map(Pairer::expire_all) {
+            self.push_unpaired(unpaired, output);
+        }
+        if let Some((summary, unpaired_count)) = self.pairer.as_mut().and_then(Pairer::summary) {
+            let row = self
+                .table
+                .print_table_line()
+                .column(column::KIND, "Pairs")
+                .column(column::PAIR, summary)
+                .highlight(unpaired_count > 0)
+                .finish();
+            output.push(Output::Row(row));
+        }
+        if let Some(modifier_checker) = self.modifier_checker.as_mut() {
+            let (summary, disagreement_count) = modifier_checker.summary();
+            let row = self
+                .table
+                .print_table_line()
+                .column(column::KIND, "Mods")
+                .column(column::MOD_CHECK, summary)
+                .highlight(disagreement_count > 0)
+                .finish();
+            output.push(Output::Row(row));
+        }
+        if let Some(dead_key_tracker) = self.dead_key_tracker.as_mut() {
+            let (summary, problem_count) = dead_key_tracker.summary();
+            let row = self
+                .table
+                .print_table_line()
+                .column(column::KIND, "Dead")
+                .column(column::DEAD_KEYS, summary)
+                .highlight(problem_count > 0)
+                .finish();
+            output.push(Output::Row(row));
+        }
+        if let Some(key_timings) = self.key_timings.as_mut() {
+            for (key_code, summary) in key_timings.summary() {
+                let row = self
+                    .table
+                    .print_table_line()
+                    .column(column::KIND, "Timing")
+                    .column_with(column::KEY_CODE, || {
+                        Cell::typed(key_code_to_string(&key_code), &key_code)
+                    })
+                    .column(column::TIMING, summary)
+                    .finish();
+                output.push(Output::Row(row));
+            }
+        }
+    }
+}
+
+/// The state of a repeated key. The JSON output gets the repeat count as a
+/// number.
+fn repeat_cell(repeat_count: i32) -> Cell {
+    Cell::typed(
+        format!("Rpt {:>4}", repeat_count),
+        &serde_json::json!({ "Repeat": repeat_count }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        build_table,
+        chords::{Chord, ChordDetector},
+        config::KeyBindings,
+        record::{device_key, key},
+    };
+
+    fn states(output: &[Output]) -> Vec<String> {
+        output
+            .iter()
+            .map(|output| match output {
+                Output::Row(row) => row.get(column::STATE).unwrap_or("").to_string(),
+                Output::UpdateRow(row) => format!("~{}", row.get(column::STATE).unwrap_or("")),
+                Output::NewTable => "NewTable".to_string(),
+            })
+            .collect()
+    }
+
+    fn repeats_enabled(session: &Session) -> bool {
+        session
+            .table()
+            .columns
+            .iter()
+            .any(|column| column.header == column::REPEATS && column.enabled)
+    }
+
+    #[test]
+    fn table_splits_after_timeout() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Pressed, false));
+        let released = start + Duration::from_millis(100);
+        session.handle_event(released, &key(KeyCode::KeyA, ElementState::Released, false));
+
+        assert!(session.tick(released + TABLE_TIMEOUT / 2).is_empty());
+        assert!(matches!(
+            session.table_timeout(released + TABLE_TIMEOUT / 2),
+            TableTimeout::Pending(remaining) if remaining == TABLE_TIMEOUT / 2
+        ));
+        assert_eq!(
+            states(&session.tick(released + TABLE_TIMEOUT)),
+            ["NewTable"]
+        );
+        assert!(session.tick(released + TABLE_TIMEOUT * 2).is_empty());
+    }
+
+    #[test]
+    fn held_keys_keep_the_table_open() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Pressed, false));
+
+        assert!(matches!(
+            session.table_timeout(start + TABLE_TIMEOUT * 2),
+            TableTimeout::Inactive
+        ));
+        assert!(session.tick(start + TABLE_TIMEOUT * 2).is_empty());
+    }
+
+    #[test]
+    fn repeats_update_a_single_row() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+
+        let mut output = Vec::new();
+        for (i, (state, repeat)) in [
+            (ElementState::Pressed, false),
+            (ElementState::Pressed, true),
+            (ElementState::Pressed, true),
+            (ElementState::Released, false),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let now = start + Duration::from_millis(30 * i as u64);
+            output.extend(session.handle_event(now, &key(KeyCode::KeyA, *state, *repeat)));
+        }
+
+        assert_eq!(
+            states(&output),
+            ["Pressed", "~Rpt    1", "~Rpt    2", "Released"]
+        );
+    }
+
+    #[test]
+    fn repeat_modes() {
+        let start = Instant::now();
+        let events = [
+            (ElementState::Pressed, false),
+            (ElementState::Pressed, true),
+            (ElementState::Pressed, true),
+            (ElementState::Released, false),
+        ];
+        let run = |repeat_mode| {
+            let mut table = build_table();
+            table.column_mut(column::REPEATS).unwrap().enabled = true;
+            let mut session = Session::new(table, start);
+            session.set_repeat_mode(repeat_mode);
+            let mut output = Vec::new();
+            for (i, (state, repeat)) in events.iter().enumerate() {
+                let now = start + Duration::from_millis(500 + 30 * i as u64);
+                output.extend(session.handle_event(now, &key(KeyCode::KeyA, *state, *repeat)));
+            }
+            output
+        };
+
+        let output = run(RepeatMode::Raw);
+        assert_eq!(
+            states(&output),
+            ["Pressed", "Rpt    1", "Rpt    2", "Released"]
+        );
+
+        let output = run(RepeatMode::Detailed);
+        assert_eq!(
+            states(&output),
+            ["Pressed", "~Rpt    1", "~Rpt    2", "Released"]
+        );
+        let release = match &output[3] {
+            Output::Row(row) => row.get(column::REPEATS),
+            _ => None,
+        };
+        assert_eq!(release, Some("2 repeats, delay 30.0ms, every 30.0ms"));
+    }
+
+    #[test]
+    fn repeat_mode_cycles_at_runtime() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Pressed, false));
+
+        let output = session.handle_event(start, &RecordedEvent::RepeatModeCycle);
+        assert_eq!(states(&output), ["NewTable", "Detailed"]);
+        assert!(repeats_enabled(&session));
+
+        let output = session.handle_event(start, &RecordedEvent::RepeatModeCycle);
+        assert_eq!(states(&output), ["NewTable", "Raw"]);
+        assert!(!repeats_enabled(&session));
+        let output = session.handle_event(start, &key(KeyCode::KeyA, ElementState::Pressed, true));
+        assert_eq!(states(&output), ["Rpt    1"]);
+
+        let output = session.handle_event(start, &RecordedEvent::RepeatModeCycle);
+        assert_eq!(states(&output), ["Collapsed"]);
+        let output = session.handle_event(start, &RecordedEvent::RepeatModeCycle);
+        assert_eq!(states(&output), ["NewTable", "Detailed"]);
+        assert!(repeats_enabled(&session));
+
+        // The repeats of a key pressed before the detailed mode aren't
+        // described.
+        let output =
+            session.handle_event(start, &key(KeyCode::KeyA, ElementState::Released, false));
+        assert_eq!(states(&output), ["Released"]);
+        match &output[0] {
+            Output::Row(row) => assert_eq!(row.get(column::REPEATS), None),
+            _ => panic!("{:?}", output),
+        }
+    }
+
+    #[test]
+    fn repeats_column_of_the_options_stays_on() {
+        let start = Instant::now();
+        let mut table = build_table();
+        table.column_mut(column::REPEATS).unwrap().enabled = true;
+        let mut session = Session::new(table, start);
+        session.set_repeat_mode(RepeatMode::Detailed);
+
+        for mode in ["Raw", "Collapsed", "Detailed"].iter() {
+            let output = session.handle_event(start, &RecordedEvent::RepeatModeCycle);
+            assert_eq!(states(&output), [*mode]);
+            assert!(repeats_enabled(&session));
+        }
+    }
+
+    #[test]
+    fn table_control_chords_leave_no_trace() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+        let mut chord_detector = ChordDetector::new(&KeyBindings {
+            table_control: Some(Chord {
+                held: vec![KeyCode::ControlRight],
+                key: KeyCode::Enter,
+            }),
+            ..KeyBindings::default()
+        });
+        let mut handle = |now, event| match chord_detector.handle_event(event) {
+            Some(event) => session.handle_event(now, &event),
+            None => Vec::new(),
+        };
+
+        for (key_code, state) in [
+            (KeyCode::ControlRight, ElementState::Pressed),
+            (KeyCode::Enter, ElementState::Pressed),
+            (KeyCode::Enter, ElementState::Released),
+            (KeyCode::ControlRight, ElementState::Released),
+        ]
+        .iter()
+        {
+            handle(start, device_key(*key_code, *state));
+            handle(start, key(*key_code, *state, false));
+        }
+        assert_eq!(states(&session.tick(start)), ["NewTable"]);
+        // The releases of the held key don't begin a table of their own.
+        assert!(session.tick(start + TABLE_TIMEOUT).is_empty());
+
+        let later = start + TABLE_TIMEOUT * 2;
+        session.handle_event(later, &key(KeyCode::KeyA, ElementState::Pressed, false));
+        session.handle_event(later, &key(KeyCode::KeyA, ElementState::Released, false));
+        assert!(session.tick(later + TABLE_TIMEOUT / 2).is_empty());
+        assert_eq!(states(&session.tick(later + TABLE_TIMEOUT)), ["NewTable"]);
+    }
+
+    #[test]
+    fn manual_mode_ignores_the_timeout() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+
+        assert!(session
+            .handle_event(start, &RecordedEvent::TableControl)
+            .is_empty());
+        assert!(session.manual_mode());
+
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Pressed, false));
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Released, false));
+        assert!(session.tick(start + TABLE_TIMEOUT * 2).is_empty());
+
+        let end = start + TABLE_TIMEOUT * 3;
+        let output = session.handle_event(end, &RecordedEvent::TableControl);
+        assert_eq!(states(&output), ["NewTable"]);
+        assert!(session.manual_mode());
+
+        session.handle_event(end, &RecordedEvent::TableControl);
+        assert!(!session.manual_mode());
+    }
+
+    #[test]
+    fn ime_toggle_switches_whether_ime_is_allowed() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+
+        let output = session.handle_event(start, &RecordedEvent::ImeToggle);
+        assert!(session.ime_allowed());
+        assert_eq!(states(&output), ["Allow"]);
+        let output = session.handle_event(start, &RecordedEvent::ImePosition);
+        assert_eq!(states(&output), ["Position"]);
+        let output = session.handle_event(start, &RecordedEvent::ImeToggle);
+        assert!(!session.ime_allowed());
+        assert_eq!(states(&output), ["Disallow"]);
+    }
+
+    #[test]
+    fn key_rows_show_changed_modifiers() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+        session.set_key_modifiers(true);
+
+        let mut modifiers = Vec::new();
+        for event in [
+            key(KeyCode::KeyA, ElementState::Pressed, false),
+            RecordedEvent::ModifiersChanged {
+                modifiers: ModifiersState::SHIFT,
+            },
+            key(KeyCode::KeyA, ElementState::Released, false),
+            key(KeyCode::KeyB, ElementState::Pressed, false),
+            RecordedEvent::ModifiersChanged {
+                modifiers: ModifiersState::empty(),
+            },
+            key(KeyCode::KeyB, ElementState::Released, false),
+        ]
+        .iter()
+        {
+            for output in session.handle_event(start, event) {
+                if let Output::Row(row) = output {
+                    modifiers.push(row.get(column::MODIFIERS).unwrap_or("").to_string());
+                }
+            }
+        }
+
+        assert_eq!(modifiers, ["", "SH", "*SH", "SH", "", "*"]);
+    }
+
+    #[test]
+    fn device_events_are_ignored_without_focus() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+
+        session.handle_event(start, &RecordedEvent::Focused { focused: false });
+        let output = session.handle_event(start, &device_key(KeyCode::KeyA, ElementState::Pressed));
+
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn idle_gap_splits_before_the_next_event() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+        session.set_split_policy(SplitPolicy::IdleGap);
+
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Pressed, false));
+        let later = start + TABLE_TIMEOUT * 2;
+        assert!(session.tick(later).is_empty());
+
+        // The key is still held, but the gap is long enough.
+        let output = session.handle_event(later, &key(KeyCode::KeyB, ElementState::Pressed, false));
+        assert_eq!(states(&output), ["NewTable", "Pressed"]);
+    }
+
+    #[test]
+    fn event_count_splits_tables() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+        session.set_split_policy(SplitPolicy::Events(3));
+
+        let mut output = Vec::new();
+        for code in [KeyCode::KeyA, KeyCode::KeyB].iter() {
+            for state in [ElementState::Pressed, ElementState::Released].iter() {
+                output.extend(session.handle_event(start, &key(*code, *state, false)));
+            }
+        }
+
+        assert_eq!(
+            states(&output),
+            ["Pressed", "Released", "Pressed", "NewTable", "Released"]
+        );
+    }
+
+    #[test]
+    fn never_waits_for_table_control() {
+        let start = Instant::now();
+        let mut session = Session::new(build_table(), start);
+        session.set_split_policy(SplitPolicy::Never);
+
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Pressed, false));
+        session.handle_event(start, &key(KeyCode::KeyA, ElementState::Released, false));
+        assert!(session.tick(start + TABLE_TIMEOUT * 2).is_empty());
+
+        let end = start + TABLE_TIMEOUT * 3;
+        session.handle_event(end, &RecordedEvent::TableControl);
+        assert_eq!(states(&session.tick(end)), ["NewTable"]);
+    }
+
+    #[test]
+    fn rows_are_timed_from_the_beginning_of_their_table() {
+        let start = Instant::now();
+        let mut table = build_table();
+        for header in [column::TIME, column::DELTA].iter() {
+            table.column_mut(header).unwrap().enabled = true;
+        }
+        let mut session = Session::new(table, start);
+        session.set_split_policy(SplitPolicy::Events(2));
+
+        let mut times = Vec::new();
+        for (ms, state) in [
+            (100, ElementState::Pressed),
+            (150, ElementState::Released),
+            (400, ElementState::Pressed),
+        ]
+        .iter()
+        {
+            let now = start + Duration::from_millis(*ms);
+            for output in session.handle_event(now, &key(KeyCode::KeyA, *state, false)) {
+                if let Output::Row(row) = output {
+                    let time = row.get(column::TIME).unwrap().to_string();
+                    times.push((time, row.get(column::DELTA).unwrap().to_string()));
+                }
+            }
+        }
+
+        let times: Vec<_> = times
+            .iter()
+            .map(|(t, d)| (t.as_str(), d.as_str()))
+            .collect();
+        assert_eq!(
+            times,
+            [
+                ("0.0ms", "+0.0ms"),
+                ("50.0ms", "+50.0ms"),
+                ("0.0ms", "+0.0ms")
+            ]
+        );
+    }
+
+    #[test]
+    fn split_policies_parse() {
+        assert_eq!("idle".parse(), Ok(SplitPolicy::IdleGap));
+        assert_eq!("Focus".parse(), Ok(SplitPolicy::Focus));
+        assert_eq!("events:20".parse(), Ok(SplitPolicy::Events(20)));
+        assert!("events".parse::<SplitPolicy>().is_err());
+        assert!("events:0".parse::<SplitPolicy>().is_err());
+        assert!("timeout:3".parse::<SplitPolicy>().is_err());
+    }
+}
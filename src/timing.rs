@@ -0,0 +1,278 @@
+//! Statistics on how long keys are held and how they repeat.
+//!
+//! [`KeyTimings`] sums up every key of a table, while [`describe_repeats`]
+//! describes the repeats of a single press. Only window events are measured,
+//! since device events don't repeat on every platform. A key press that spans
+//! two tables counts towards the table its release or repeats are in.
+
+use std::{collections::HashMap, time::Instant};
+
+use serde_json::json;
+use winit::{event::ElementState, keyboard::KeyCode};
+
+use crate::{record::RecordedEvent, Cell};
+
+/// When a held key was pressed and when it last repeated.
+struct Press {
+    pressed: Instant,
+    last_repeat: Option<Instant>,
+}
+
+/// The timings of a key in the current table, in milliseconds.
+#[derive(Default)]
+struct KeyStats {
+    held: Vec<f64>,
+    /// The time from each press to its first repeat.
+    delays: Vec<f64>,
+    /// The time between repeats.
+    intervals: Vec<f64>,
+}
+
+pub struct KeyTimings {
+    held: HashMap<KeyCode, Press>,
+    /// The keys in the current table, in the order they were measured.
+    stats: Vec<(KeyCode, KeyStats)>,
+}
+
+impl KeyTimings {
+    pub fn new() -> Self {
+        Self {
+            held: HashMap::new(),
+            stats: Vec::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, now: Instant, event: &RecordedEvent) {
+        let event = match event {
+            RecordedEvent::KeyboardInput { event, .. } => event,
+            // A key released in another window has no release to end its
+            // hold time, so it isn't measured.
+            RecordedEvent::Focused { focused: false } => {
+                self.held.clear();
+                return;
+            }
+            // The releases of a table control chord's keys are taken out.
+            RecordedEvent::TableControl => {
+                self.held.clear();
+                return;
+            }
+            _ => return,
+        };
+        let key_code = event.physical_key;
+        match (event.state, event.repeat) {
+            (ElementState::Pressed, false) => {
+                self.held.insert(
+                    key_code,
+                    Press {
+                        pressed: now,
+                        last_repeat: None,
+                    },
+                );
+            }
+            (ElementState::Pressed, true) => {
+                let press = match self.held.get_mut(&key_code) {
+                    Some(press) => press,
+                    None => return,
+                };
+                let since_last = millis(now, press.last_repeat.unwrap_or(press.pressed));
+                let first_repeat = press.last_repeat.replace(now).is_none();
+                let stats = self.stats_mut(key_code);
+                if first_repeat {
+                    stats.delays.push(since_last);
+                } else {
+                    stats.intervals.push(since_last);
+                }
+            }
+            (ElementState::Released, _) => {
+                if let Some(press) = self.held.remove(&key_code) {
+                    let held = millis(now, press.pressed);
+                    self.stats_mut(key_code).held.push(held);
+                }
+            }
+        }
+    }
+
+    fn stats_mut(&mut self, key_code: KeyCode) -> &mut KeyStats {
+        let index = match self.stats.iter().position(|(key, _)| *key == key_code) {
+            Some(index) => index,
+            None => {
+                self.stats.push((key_code, KeyStats::default()));
+                self.stats.len() - 1
+            }
+        };
+        &mut self.stats[index].1
+    }
+
+    /// Sums up the timings of every key since the last summary.
+    pub fn summary(&mut self) -> Vec<(KeyCode, Cell)> {
+        std::mem::take(&mut self.stats)
+            .into_iter()
+            .map(|(key_code, stats)| {
+                let mut text = Vec::new();
+                if !stats.held.is_empty() {
+                    let presses = match stats.held.len() {
+                        1 => "1 press".to_string(),
+                        count => format!("{} presses", count),
+                    };
+                    text.push(format!("{}, held {}", presses, describe(&stats.held)));
+                }
+                if !stats.delays.is_empty() {
+                    text.push(format!("repeat delay {}", describe(&stats.delays)));
+                }
+                if !stats.intervals.is_empty() {
+                    text.push(format!(
+                        "repeat rate {:.1}/s, every {}",
+                        1000.0 / average(&stats.intervals),
+                        describe(&stats.intervals)
+                    ));
+                }
+                let value = json!({
+                    "held_ms": stats.held,
+                    "repeat_delay_ms": stats.delays,
+                    "repeat_interval_ms": stats.intervals,
+                });
+                (key_code, Cell::typed(text.join(", "), &value))
+            })
+            .collect()
+    }
+}
+
+/// Describes the repeats of a single press, given the time of the press
+/// followed by the time of every repeat. Returns `None` if the key didn't
+/// repeat.
+///
+/// The jitter is the standard deviation of the time between repeats.
+pub fn describe_repeats(times: &[Instant]) -> Option<Cell> {
+    let (pressed, repeats) = times.split_first()?;
+    let first_repeat = repeats.first()?;
+    let delay = millis(*first_repeat, *pressed);
+    let intervals: Vec<_> = repeats
+        .windows(2)
+        .map(|pair| millis(pair[1], pair[0]))
+        .collect();
+
+    let mut text = match repeats.len() {
+        1 => "1 repeat".to_string(),
+        count => format!("{} repeats", count),
+    };
+    text.push_str(&format!(", delay {:.1}ms", delay));
+    if !intervals.is_empty() {
+        text.push_str(&format!(", every {}", describe(&intervals)));
+    }
+    let jitter = if intervals.len() > 1 {
+        let mean = average(&intervals);
+        let variance = intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>()
+            / intervals.len() as f64;
+        Some(variance.sqrt())
+    } else {
+        None
+    };
+    if let Some(jitter) = jitter {
+        text.push_str(&format!(", jitter {:.1}ms", jitter));
+    }
+
+    let value = json!({
+        "repeats": repeats.len(),
+        "delay_ms": delay,
+        "interval_ms": intervals,
+        "jitter_ms": jitter,
+    });
+    Some(Cell::typed(text, &value))
+}
+
+fn millis(now: Instant, earlier: Instant) -> f64 {
+    now.duration_since(earlier).as_secs_f64() * 1000.0
+}
+
+fn average(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The average of `values`, along with their range if there's more than one.
+fn describe(values: &[f64]) -> String {
+    if values.len() == 1 {
+        return format!("{:.1}ms", values[0]);
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    format!("{:.1}ms ({:.1} to {:.1}ms)", average(values), min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::record::key;
+
+    #[test]
+    fn presses_and_repeats_are_measured_per_key() {
+        let start = Instant::now();
+        let mut timings = KeyTimings::new();
+        let mut handle = |ms, key_code, state, repeat| {
+            timings.handle_event(
+                start + Duration::from_millis(ms),
+                &key(key_code, state, repeat),
+            )
+        };
+
+        handle(0, KeyCode::KeyA, ElementState::Pressed, false);
+        handle(500, KeyCode::KeyA, ElementState::Pressed, true);
+        handle(530, KeyCode::KeyA, ElementState::Pressed, true);
+        handle(570, KeyCode::KeyA, ElementState::Pressed, true);
+        handle(600, KeyCode::KeyA, ElementState::Released, false);
+        handle(700, KeyCode::KeyB, ElementState::Pressed, false);
+        handle(800, KeyCode::KeyB, ElementState::Released, false);
+        handle(900, KeyCode::KeyB, ElementState::Pressed, false);
+        handle(1200, KeyCode::KeyB, ElementState::Released, false);
+
+        let summary: Vec<_> = timings
+            .summary()
+            .into_iter()
+            .map(|(key_code, cell)| (key_code, cell.text))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    KeyCode::KeyA,
+                    "1 press, held 600.0ms, repeat delay 500.0ms, \
+                     repeat rate 28.6/s, every 35.0ms (30.0 to 40.0ms)"
+                        .to_string()
+                ),
+                (
+                    KeyCode::KeyB,
+                    "2 presses, held 200.0ms (100.0 to 300.0ms)".to_string()
+                ),
+            ]
+        );
+        assert!(timings.summary().is_empty());
+    }
+
+    #[test]
+    fn repeats_of_a_press_are_described() {
+        let start = Instant::now();
+        let times = |ms: &[u64]| -> Vec<_> {
+            ms.iter()
+                .map(|ms| start + Duration::from_millis(*ms))
+                .collect()
+        };
+        let describe = |ms: &[u64]| describe_repeats(&times(ms)).map(|cell| cell.text);
+
+        assert_eq!(describe(&[0]), None);
+        assert_eq!(
+            describe(&[0, 500]).as_deref(),
+            Some("1 repeat, delay 500.0ms")
+        );
+        assert_eq!(
+            describe(&[0, 500, 530, 570, 600]).as_deref(),
+            Some(
+                "4 repeats, delay 500.0ms, every 33.3ms (30.0 to 40.0ms), \
+                 jitter 4.7ms"
+            )
+        );
+    }
+}
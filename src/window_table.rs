@@ -0,0 +1,162 @@
+//! Draws the tables into the window, for when there's no terminal to print
+//! them to.
+
+use std::collections::VecDeque;
+
+use crate::{
+    canvas::{Canvas, BLACK, WHITE},
+    IoWriteTablePrinter, Row, Table, TablePrinter,
+};
+
+/// Older lines are dropped once there are more than this.
+const MAX_LINES: usize = 1000;
+
+const HEADER_COLOR: u32 = 0x0000_3399;
+const HEADER_BACKGROUND: u32 = 0x00e0_e8f0;
+/// The background of a row that may still be updated by key repeats.
+const UPDATING_BACKGROUND: u32 = 0x00ff_f4d0;
+const HIGHLIGHTED_BACKGROUND: u32 = 0x00ff_d8d8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LineKind {
+    Header,
+    Row,
+    HighlightedRow,
+    Blank,
+}
+
+/// Keeps the most recent lines of the tables as markdown and draws as many of
+/// them as fit into the window, so that it scrolls as rows are added.
+pub struct WindowTablePrinter {
+    lines: VecDeque<(LineKind, String)>,
+    updating: bool,
+    ioprinter: IoWriteTablePrinter,
+}
+
+impl WindowTablePrinter {
+    pub fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            updating: false,
+            ioprinter: IoWriteTablePrinter::new(),
+        }
+    }
+
+    /// Draws the lines that fit into the top `height` pixels of the canvas.
+    pub fn draw(&self, canvas: &mut Canvas<'_>, height: usize) {
+        let width = canvas.width();
+        canvas.fill_rect(0, 0, width, height, WHITE);
+
+        let line_height = canvas.line_height();
+        let visible_lines = height / line_height;
+        let skipped_lines = self.lines.len().saturating_sub(visible_lines);
+
+        for (i, (kind, text)) in self.lines.iter().skip(skipped_lines).enumerate() {
+            let y = i * line_height;
+            let is_last = skipped_lines + i + 1 == self.lines.len();
+            let color = match kind {
+                LineKind::Header => {
+                    canvas.fill_rect(0, y, width, line_height, HEADER_BACKGROUND);
+                    HEADER_COLOR
+                }
+                LineKind::HighlightedRow => {
+                    canvas.fill_rect(0, y, width, line_height, HIGHLIGHTED_BACKGROUND);
+                    BLACK
+                }
+                LineKind::Row if is_last && self.updating => {
+                    canvas.fill_rect(0, y, width, line_height, UPDATING_BACKGROUND);
+                    BLACK
+                }
+                LineKind::Row | LineKind::Blank => BLACK,
+            };
+            canvas.draw_text(0, y, text, color);
+        }
+    }
+
+    fn push_line(&mut self, kind: LineKind, text: String) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back((kind, text));
+    }
+
+    fn format_row(&mut self, table: &Table, row: &Row) -> (LineKind, String) {
+        let mut buffer = Vec::new();
+        self.ioprinter.print_row(table, row, &mut buffer);
+        let kind = if row.highlighted {
+            LineKind::HighlightedRow
+        } else {
+            LineKind::Row
+        };
+        (kind, String::from_utf8(buffer).unwrap())
+    }
+}
+
+impl TablePrinter for WindowTablePrinter {
+    fn begin_new_table(&mut self, table: &Table) {
+        if !self.lines.is_empty() {
+            self.push_line(LineKind::Blank, String::new());
+        }
+        self.updating = false;
+
+        let mut buffer = Vec::new();
+        self.ioprinter.begin_new_table(table, &mut buffer);
+        for line in String::from_utf8(buffer).unwrap().lines() {
+            self.push_line(LineKind::Header, line.to_string());
+        }
+    }
+
+    fn print_row(&mut self, table: &Table, row: &Row) {
+        let (kind, line) = self.format_row(table, row);
+        self.push_line(kind, line);
+        self.updating = false;
+    }
+
+    fn update_row(&mut self, table: &Table, row: &Row) {
+        let line = self.format_row(table, row);
+        match self.lines.back_mut() {
+            Some(last_line) if self.updating => *last_line = line,
+            _ => self.push_line(line.0, line.1),
+        }
+        self.updating = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_table, column};
+
+    #[test]
+    fn updates_replace_the_last_row() {
+        let table = build_table();
+        let row = |state| {
+            table
+                .print_table_line()
+                .column(column::STATE, state)
+                .finish()
+        };
+
+        let mut window_printer = WindowTablePrinter::new();
+        window_printer.begin_new_table(&table);
+        window_printer.print_row(&table, &row("Pressed"));
+        window_printer.update_row(&table, &row("Rpt    1"));
+        window_printer.update_row(&table, &row("Rpt    2"));
+        window_printer.print_row(&table, &row("Released"));
+        window_printer.begin_new_table(&table);
+
+        let kinds: Vec<_> = window_printer.lines.iter().map(|(kind, _)| *kind).collect();
+        let (header, row, blank) = (LineKind::Header, LineKind::Row, LineKind::Blank);
+        assert_eq!(
+            kinds,
+            [header, header, row, row, row, blank, header, header]
+        );
+        assert!(window_printer.lines[3].1.contains("Rpt    2"));
+
+        let (width, height) = (400, 100);
+        let mut pixels = vec![0; width * height];
+        window_printer.draw(&mut Canvas::new(&mut pixels, width, height, 1), height);
+        assert!(pixels.contains(&HEADER_COLOR));
+        assert!(pixels.contains(&BLACK));
+    }
+}